@@ -0,0 +1,50 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre;
+use color_eyre::eyre::WrapErr;
+use tar::Archive;
+
+use crate::compression::decompress;
+
+/// Entry of the archive with the meta file of the toolbox, next to its root filesystem
+pub const META_ENTRY: &str = ".unbox-meta.toml";
+
+/// Unpacks a tarball into the new root, returning the configuration if it is an exported toolbox.
+/// The owners of the files are only kept when `ownership` is set, otherwise the user owns them all
+pub fn unpack_tar(tar: PathBuf, new_root: &str, ownership: bool) -> eyre::Result<Option<String>> {
+    let archive = File::open(tar).wrap_err("Could not open the tar file")?;
+    let mut tar = Archive::new(decompress(BufReader::new(archive))?);
+    tar.set_preserve_ownerships(ownership);
+    tar.set_preserve_permissions(ownership);
+    let mut dirs = Vec::new();
+    let mut meta = None;
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?;
+        if path == Path::new(META_ENTRY) {
+            let mut content = String::new();
+            entry
+                .read_to_string(&mut content)
+                .wrap_err("Could not read the configuration of the toolbox")?;
+            meta = Some(content);
+        } else if path.is_dir() {
+            dirs.push(entry);
+        } else {
+            entry
+                .unpack_in(new_root)
+                .wrap_err("Could not unpack entry")?;
+        }
+    }
+    dirs.sort_unstable_by_key(|b| std::cmp::Reverse(b.path_bytes().len()));
+    for mut dir in dirs {
+        dir.unpack_in(new_root)
+            .wrap_err("Could not unpack a directory")?;
+    }
+    Ok(meta)
+}
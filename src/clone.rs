@@ -0,0 +1,63 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use clap::Args;
+use color_eyre::eyre;
+use color_eyre::eyre::WrapErr;
+
+use crate::config::{meta_path, Config};
+use crate::copy::{copy_tree, INDEPENDENT};
+use crate::namespaces::enter_user_namespace;
+use crate::session::Session;
+
+/// Create a toolbox as a copy of another one
+#[derive(Args, PartialEq, Eq, Debug)]
+pub struct CloneToolbox {
+    #[clap(value_parser)]
+    /// Name of the toolbox to copy
+    pub source: String,
+    #[clap(value_parser)]
+    /// Name of the new toolbox
+    pub name: String,
+}
+
+pub fn clone(args: CloneToolbox) -> eyre::Result<()> {
+    let CloneToolbox { source, name } = args;
+    let mut config = Config::find(&source)?;
+    let from = config.image.clone();
+    eyre::ensure!(
+        Path::new(&from).is_dir(),
+        "The image of {source} does not exist"
+    );
+    eyre::ensure!(
+        Session::live(&source)?.is_empty(),
+        "The toolbox {source} is still running, stop it with `unbox stop {source}` first"
+    );
+    eyre::ensure!(
+        !meta_path(&name)?.exists(),
+        "There is already a toolbox with that name"
+    );
+    let image = Config::new(&name)?.image;
+    eyre::ensure!(
+        !Path::new(&image).exists(),
+        "There is already an image with that name"
+    );
+    // Files of the subordinate ids are copied with their owners
+    enter_user_namespace()?;
+    if let Some(parent) = Path::new(&image).parent() {
+        std::fs::create_dir_all(parent).wrap_err("Could not create the images directory")?;
+    }
+    // The clone cannot share its files with the source
+    copy_tree(Path::new(&from), Path::new(&image), &INDEPENDENT)?;
+    config.image = image;
+    config.hostname = name.clone();
+    config.created = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_secs());
+    config.write(&name)
+}
@@ -0,0 +1,130 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::io::{self, BufRead, Read, Write};
+use std::path::Path;
+
+use color_eyre::eyre;
+use color_eyre::eyre::WrapErr;
+
+const GZIP: &[u8] = &[0x1f, 0x8b];
+const XZ: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0x00];
+const ZSTD: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const BZIP2: &[u8] = b"BZh";
+
+/// Compression formats that can be detected from the first bytes of a stream
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Compression {
+    None,
+    Gzip,
+    Xz,
+    Zstd,
+    Bzip2,
+}
+
+impl Compression {
+    pub fn detect(magic: &[u8]) -> Self {
+        if magic.starts_with(GZIP) {
+            Compression::Gzip
+        } else if magic.starts_with(XZ) {
+            Compression::Xz
+        } else if magic.starts_with(ZSTD) {
+            Compression::Zstd
+        } else if magic.starts_with(BZIP2) {
+            Compression::Bzip2
+        } else {
+            Compression::None
+        }
+    }
+}
+
+/// Wraps the reader with a streaming decoder for the compression format it uses, if any
+pub fn decompress<'a, R>(mut reader: R) -> eyre::Result<Box<dyn Read + 'a>>
+where
+    R: BufRead + 'a,
+{
+    let magic = reader.fill_buf().wrap_err("Could not read the archive")?;
+    let decoder: Box<dyn Read + 'a> = match Compression::detect(magic) {
+        Compression::None => Box::new(reader),
+        // Multi-member decoders are used because some tools produce concatenated streams
+        Compression::Gzip => Box::new(flate2::bufread::MultiGzDecoder::new(reader)),
+        Compression::Xz => Box::new(xz2::bufread::XzDecoder::new_multi_decoder(reader)),
+        Compression::Zstd => {
+            Box::new(zstd::Decoder::with_buffer(reader).wrap_err("Could not read zstd archive")?)
+        }
+        Compression::Bzip2 => Box::new(bzip2::bufread::MultiBzDecoder::new(reader)),
+    };
+    Ok(decoder)
+}
+
+impl Compression {
+    /// Format for a file name ending in `.gz`, `.xz`, `.zst` or `.bz2`, or none
+    pub fn from_extension(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("gz" | "tgz") => Compression::Gzip,
+            Some("xz" | "txz") => Compression::Xz,
+            Some("zst" | "zstd") => Compression::Zstd,
+            Some("bz2" | "tbz2") => Compression::Bzip2,
+            _ => Compression::None,
+        }
+    }
+}
+
+/// Streaming encoder, it has to be finished to write the end of the compressed stream
+pub enum Encoder<W: Write> {
+    None(W),
+    Gzip(flate2::write::GzEncoder<W>),
+    Xz(xz2::write::XzEncoder<W>),
+    Zstd(zstd::Encoder<'static, W>),
+    Bzip2(bzip2::write::BzEncoder<W>),
+}
+
+impl<W: Write> Encoder<W> {
+    pub fn new(writer: W, compression: Compression) -> eyre::Result<Self> {
+        let encoder = match compression {
+            Compression::None => Encoder::None(writer),
+            Compression::Gzip => {
+                Encoder::Gzip(flate2::write::GzEncoder::new(writer, Default::default()))
+            }
+            Compression::Xz => Encoder::Xz(xz2::write::XzEncoder::new(writer, 6)),
+            Compression::Zstd => Encoder::Zstd(
+                zstd::Encoder::new(writer, 0).wrap_err("Could not start the zstd stream")?,
+            ),
+            Compression::Bzip2 => {
+                Encoder::Bzip2(bzip2::write::BzEncoder::new(writer, Default::default()))
+            }
+        };
+        Ok(encoder)
+    }
+
+    pub fn finish(self) -> io::Result<W> {
+        match self {
+            Encoder::None(writer) => Ok(writer),
+            Encoder::Gzip(encoder) => encoder.finish(),
+            Encoder::Xz(encoder) => encoder.finish(),
+            Encoder::Zstd(encoder) => encoder.finish(),
+            Encoder::Bzip2(encoder) => encoder.finish(),
+        }
+    }
+
+    fn writer(&mut self) -> &mut dyn Write {
+        match self {
+            Encoder::None(writer) => writer,
+            Encoder::Gzip(encoder) => encoder,
+            Encoder::Xz(encoder) => encoder,
+            Encoder::Zstd(encoder) => encoder,
+            Encoder::Bzip2(encoder) => encoder,
+        }
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer().flush()
+    }
+}
@@ -2,17 +2,26 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use std::collections::BTreeMap;
 use std::env;
 use std::ffi::OsString;
+use std::fmt::Display;
 use std::fs::{create_dir_all, File};
+use std::path::PathBuf;
+use std::str::FromStr;
 
-use clap::Args;
+use clap::{Args, ValueEnum};
 use color_eyre::eyre;
 use color_eyre::eyre::WrapErr;
 use serde::{Deserialize, Serialize};
 use toml::map::Keys;
 use toml::value::{Table, Value};
 
+use crate::environment;
+use crate::namespaces::{UserNs, OLD_ROOT};
+use crate::network::Network;
+
+/// Storage directory used when no other one is configured, relative to the home directory
 pub const STORAGE: &str = ".local/share/unbox";
 
 /// Configure a toolbox creating a new meta-file if needed
@@ -30,9 +39,63 @@ pub struct Configure {
     #[clap(long, value_parser)]
     /// Default home for the image
     home: Option<String>,
+    #[clap(long, value_parser)]
+    /// How the ids of the user are mapped inside of the toolbox
+    userns: Option<UserNs>,
+    #[clap(long, value_parser)]
+    /// Isolate the processes of the toolbox in a new PID namespace
+    pidns: Option<bool>,
+    #[clap(long, value_parser)]
+    /// Network that the toolbox has access to
+    network: Option<Network>,
+    #[clap(short, long, value_parser = environment::parse_variable)]
+    /// Set a variable inside of the toolbox as KEY=VAL, or KEY to take its current value
+    env: Vec<(String, Option<String>)>,
+    #[clap(long, value_parser)]
+    /// Remove a variable set with --env
+    unset_env: Vec<String>,
+    #[clap(long, value_parser)]
+    /// Only keep the variables of the host in the allow list
+    clean_env: Option<bool>,
+    #[clap(long, value_parser = parse_mount)]
+    /// Bind mount a directory of the host, as src:dst[:ro,nosuid,noexec,private]
+    mount: Vec<MountInfo>,
+    #[clap(long, value_parser)]
+    /// Remove the mount of the given directory of the toolbox
+    unmount: Vec<String>,
+    #[clap(long, value_parser)]
+    /// Show the effective configuration and where each value comes from
+    show: bool,
+}
+
+impl Configure {
+    /// Whether any value of the configuration is changed, instead of only shown
+    fn changes(&self) -> bool {
+        let options = [
+            self.shell.is_some(),
+            self.hostname.is_some(),
+            self.home.is_some(),
+            self.userns.is_some(),
+            self.pidns.is_some(),
+            self.network.is_some(),
+            self.clean_env.is_some(),
+        ];
+        let lists = [
+            self.env.is_empty(),
+            self.unset_env.is_empty(),
+            self.mount.is_empty(),
+            self.unmount.is_empty(),
+        ];
+        options.contains(&true) || lists.contains(&false)
+    }
 }
 
 pub fn configure(args: Configure) -> eyre::Result<()> {
+    // Only showing the configuration must not create a meta file for a toolbox that does not exist
+    if args.show && !args.changes() {
+        print!("{}", Config::find(&args.name)?.show());
+        return Ok(());
+    }
     let mut config =
         Config::read_or_new(&args.name).wrap_err("Could not get configuration for the toolbox")?;
     if let Some(sh) = args.shell {
@@ -44,17 +107,107 @@ pub fn configure(args: Configure) -> eyre::Result<()> {
     if let Some(home) = args.home {
         config.home = home;
     }
+    if let Some(userns) = args.userns {
+        config.userns = userns;
+    }
+    if let Some(pidns) = args.pidns {
+        config.pidns = pidns;
+    }
+    if let Some(network) = args.network {
+        config.network = network;
+    }
+    if let Some(clean_env) = args.clean_env {
+        config.clean_env = clean_env;
+    }
+    for key in args.unset_env {
+        config.env.remove(&key);
+    }
+    for (key, value) in args.env {
+        let value = match value.or_else(|| env::var(&key).ok()) {
+            Some(value) => value,
+            None => eyre::bail!("The variable {key} is not set"),
+        };
+        config.env.insert(key, value);
+    }
+    for target in args.unmount {
+        config.unmount(&target);
+    }
+    for mount in args.mount {
+        config.mount(mount);
+    }
     config.write(&args.name)?;
+    if args.show {
+        let config = Config::read(&args.name)?;
+        print!("{}", config.show());
+    }
     Ok(())
 }
 
 pub fn setup() -> eyre::Result<()> {
-    use std::env::var;
-    let home = var("HOME").wrap_err("Could not find current home")?;
-    let meta = format!("{home}/{}/meta/", STORAGE);
-    let images = format!("{home}/{}/images/", STORAGE);
-    create_dir_all(meta).wrap_err("Could not create meta directory")?;
-    create_dir_all(images).wrap_err("Could not create images directory")
+    let storage = storage()?;
+    create_dir_all(storage.join("meta")).wrap_err("Could not create meta directory")?;
+    create_dir_all(storage.join("images")).wrap_err("Could not create images directory")
+}
+
+/// Root directory of the storage, from the first of: the `UNBOX_STORAGE` environment variable,
+/// the `storage` key of the global configuration, `$XDG_DATA_HOME/unbox` or `~/.local/share/unbox`.
+/// The last one is still used when only it exists, so toolboxes created before setting
+/// `XDG_DATA_HOME` are not lost
+pub fn storage() -> eyre::Result<PathBuf> {
+    if let Some(dir) = env::var_os("UNBOX_STORAGE").filter(|d| !d.is_empty()) {
+        return Ok(PathBuf::from(dir));
+    }
+    let configured = global_layer()?.and_then(|(_, mut global)| global.remove("storage"));
+    match configured {
+        Some(Value::String(dir)) => match dir.strip_prefix("~/") {
+            Some(relative) => return Ok(home()?.join(relative)),
+            None => return Ok(PathBuf::from(dir)),
+        },
+        Some(_) => eyre::bail!("The storage of the global config file has to be a path"),
+        None => {}
+    }
+    let legacy = home()?.join(STORAGE);
+    match env::var_os("XDG_DATA_HOME").filter(|d| !d.is_empty()) {
+        Some(dir) => {
+            let dir = PathBuf::from(dir).join("unbox");
+            match !dir.exists() && legacy.exists() {
+                true => Ok(legacy),
+                false => Ok(dir),
+            }
+        }
+        None => Ok(legacy),
+    }
+}
+
+/// Path of the meta file with the configuration of a toolbox
+pub fn meta_path(name: &str) -> eyre::Result<PathBuf> {
+    Ok(storage()?.join("meta").join(format!("{name}.toml")))
+}
+
+/// Shell of the user from `$SHELL`, the passwd database or `/bin/sh` as a last resort
+fn default_shell() -> String {
+    use users::os::unix::UserExt;
+    env::var("SHELL")
+        .ok()
+        .filter(|shell| !shell.is_empty())
+        .or_else(|| {
+            let user = users::get_user_by_uid(users::get_current_uid())?;
+            user.shell().to_str().map(String::from)
+        })
+        .unwrap_or_else(|| "/bin/sh".into())
+}
+
+fn home() -> eyre::Result<PathBuf> {
+    let home = env::var_os("HOME").ok_or_else(|| eyre::eyre!("Could not find current home"))?;
+    Ok(PathBuf::from(home))
+}
+
+/// OCI engine to extract the rootfs (docker or podman)
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Engine {
+    Docker,
+    Podman,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -63,32 +216,116 @@ pub struct Config {
     pub hostname: String,
     pub home: String,
     pub image: String,
+    /// Seconds since the Unix epoch when the toolbox was created
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<u64>,
+    /// Tarball, image reference, image layout or docker archive the toolbox was created from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Digest of the image the toolbox was created from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+    /// OCI engine that extracted the image
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub engine: Option<Engine>,
+    #[serde(default)]
+    pub userns: UserNs,
+    #[serde(default)]
+    pub pidns: bool,
+    #[serde(default)]
+    pub network: Network,
+    /// Variables set inside of the toolbox
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Only keep the variables of the host in `env_allow`, instead of all but the ones in `env_deny`
+    #[serde(default)]
+    pub clean_env: bool,
+    #[serde(default)]
+    pub env_allow: Vec<String>,
+    #[serde(default)]
+    pub env_deny: Vec<String>,
     mounts: Table,
+    /// Layer where each value comes from, nested values use dotted keys
+    #[serde(skip)]
+    origins: BTreeMap<String, Origin>,
+}
+
+/// Layer of the configuration that a value comes from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Origin {
+    Default,
+    Global(PathBuf),
+    Toolbox(PathBuf),
+}
+
+impl Display for Origin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Origin::Default => write!(f, "default"),
+            Origin::Global(path) => write!(f, "global config {path:?}"),
+            Origin::Toolbox(path) => write!(f, "toolbox config {path:?}"),
+        }
+    }
 }
 
 impl Config {
+    /// Configuration for a new toolbox: the defaults with the global configuration on top
     pub fn new(name: &str) -> eyre::Result<Self> {
-        use std::env::var;
-        let shell = var("SHELL").wrap_err("Could not find current shell")?;
-        let home = var("HOME").wrap_err("Could not find current home")?;
+        let mut layers = vec![(Origin::Default, Config::builtin(name)?)];
+        layers.extend(global_layer()?);
+        Config::merge(layers)
+    }
+
+    fn builtin(name: &str) -> eyre::Result<Table> {
+        let shell = default_shell();
+        let image = storage()?.join("images").join(name);
         let user = users::get_current_username()
             .expect("the user still exits")
             .into_string()
             .expect("Username is valid UTF8");
-        Ok(Config {
+        let config = Config {
             shell,
             hostname: name.to_string(),
             home: format!("/home/{user}"),
-            image: format!("{home}/{}/images/{name}", STORAGE),
+            image: image.to_string_lossy().into_owned(),
+            created: None,
+            source: None,
+            digest: None,
+            engine: None,
+            userns: UserNs::default(),
+            pidns: false,
+            network: Network::default(),
+            env: BTreeMap::new(),
+            clean_env: false,
+            env_allow: environment::DEFAULT_ALLOW.map(String::from).to_vec(),
+            env_deny: environment::DEFAULT_DENY.map(String::from).to_vec(),
             mounts: Config::default_mounts(),
-        })
+            origins: BTreeMap::new(),
+        };
+        Ok(config.table())
     }
 
+    /// Configuration of an existing toolbox, with its meta file on top of the other layers
     pub fn read(name: &str) -> eyre::Result<Self> {
-        let home = env::var("HOME").wrap_err("Could not find current home")?;
-        let storage = format!("{home}/{}/meta/{name}.toml", STORAGE);
-        let meta = std::fs::read_to_string(storage).wrap_err("Could not read meta file")?;
-        let config: Config = toml::from_str(&meta).wrap_err("Meta file is corrupted")?;
+        let mut layers = vec![(Origin::Default, Config::builtin(name)?)];
+        layers.extend(global_layer()?);
+        let storage = meta_path(name)?;
+        let meta = std::fs::read_to_string(&storage).wrap_err("Could not read meta file")?;
+        let meta = toml::from_str(&meta).wrap_err("Meta file is corrupted")?;
+        layers.push((Origin::Toolbox(storage), meta));
+        Config::merge(layers).wrap_err("Meta file is corrupted")
+    }
+
+    /// Configuration of a toolbox that has to exist, with a meta file or at least an image
+    pub fn find(name: &str) -> eyre::Result<Self> {
+        if meta_path(name)?.exists() {
+            return Config::read(name);
+        }
+        let config = Config::new(name)?;
+        eyre::ensure!(
+            std::path::Path::new(&config.image).exists(),
+            "There is no toolbox named {name}"
+        );
         Ok(config)
     }
 
@@ -99,16 +336,74 @@ impl Config {
         }
     }
 
+    /// Writes the values that differ from the layers beneath the meta file
     pub fn write(&self, name: &str) -> eyre::Result<()> {
         use std::io::prelude::*;
-        let home = env::var("HOME").wrap_err("Could not find current home")?;
-        let storage = format!("{home}/{}/meta/{name}.toml", STORAGE);
-        let content = toml::to_string(self).expect("valid toml config");
+        let mut layers = vec![(Origin::Default, Config::builtin(name)?)];
+        layers.extend(global_layer()?);
+        let (beneath, origins) = merge(layers);
+        let mut meta = difference(self.table(), &beneath);
+        // The root filesystem stays where it was created even if the defaults change
+        meta.insert("image".into(), Value::String(self.image.clone()));
+        // The defaults of the shell and home come from whoever runs unbox, so they are fixed when
+        // the toolbox is created instead of changing with the environment of each command
+        let pinned = [("shell", &self.shell), ("home", &self.home)];
+        for (key, value) in pinned {
+            if origins.get(key) == Some(&Origin::Default) {
+                meta.insert(key.into(), Value::String(value.clone()));
+            }
+        }
+        let storage = meta_path(name)?;
+        let content = toml::to_string(&meta).expect("valid toml config");
         let mut file = File::create(storage).wrap_err("Could not create meta file")?;
         file.write_all(content.as_bytes())?;
         Ok(())
     }
 
+    /// Effective configuration in TOML, annotated with the origin of each value
+    pub fn show(&self) -> String {
+        use std::fmt::Write;
+        let origin = |key: &str| self.origins.get(key).cloned().unwrap_or(Origin::Default);
+        let mut content = String::new();
+        let table = self.table();
+        let (tables, values): (Vec<_>, Vec<_>) = table.iter().partition(|(_, v)| v.is_table());
+        for (key, value) in values {
+            let _ = writeln!(content, "{} = {value}  # {}", bare(key), origin(key));
+        }
+        for (key, value) in tables {
+            let _ = writeln!(content, "\n[{}]", bare(key));
+            for (inner, value) in value.as_table().into_iter().flatten() {
+                let origin = origin(&format!("{key}.{inner}"));
+                let _ = writeln!(content, "{} = {value}  # {origin}", bare(inner));
+            }
+        }
+        content
+    }
+
+    fn merge(layers: Vec<(Origin, Table)>) -> eyre::Result<Self> {
+        let (table, origins) = merge(layers);
+        let mut config: Config = Value::Table(table).try_into()?;
+        config.origins = origins;
+        Ok(config)
+    }
+
+    fn table(&self) -> Table {
+        match Value::try_from(self) {
+            Ok(Value::Table(table)) => table,
+            _ => unreachable!("the configuration is a valid toml table"),
+        }
+    }
+
+    pub fn mount(&mut self, mount: MountInfo) {
+        let target = mount.target.to_string_lossy().into_owned();
+        self.mounts.insert(target, mount.value());
+    }
+
+    pub fn unmount(&mut self, target: &str) {
+        // Writing only keeps it if a layer beneath has the mount
+        self.mounts.insert(target.into(), Value::Boolean(false));
+    }
+
     pub fn mounts(&self) -> Mounts<'_> {
         Mounts {
             keys: self.mounts.keys(),
@@ -141,25 +436,64 @@ pub struct Mounts<'a> {
 impl Iterator for Mounts<'_> {
     type Item = eyre::Result<MountInfo>;
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(key) = self.keys.next() {
+        for key in self.keys.by_ref() {
             let val = self.table.get(key).unwrap();
-            let source = match val {
-                Value::String(source) => source.into(),
+            let (source, options) = match val {
+                Value::String(source) => (source.into(), MountOptions::default()),
+                // Mounts of the layers beneath can be disabled with `false`
+                Value::Boolean(false) => continue,
+                Value::Table(entry) => match MountInfo::entry(entry) {
+                    Some(entry) => entry,
+                    None => return Some(Err(eyre::eyre!("Invalid mount info entry"))),
+                },
                 _ => return Some(Err(eyre::eyre!("Invalid mount info entry"))),
             };
-            Some(Ok(MountInfo {
+            return Some(Ok(MountInfo {
                 source,
                 target: key.into(),
-            }))
-        } else {
-            None
+                options,
+            }));
         }
+        None
     }
 }
 
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub struct MountInfo {
     pub source: OsString,
     pub target: OsString,
+    pub options: MountOptions,
+}
+
+impl MountInfo {
+    fn entry(entry: &Table) -> Option<(OsString, MountOptions)> {
+        let source = entry.get("source")?.as_str()?;
+        let options = match entry.get("options") {
+            Some(Value::Array(options)) => options
+                .iter()
+                .map(|o| o.as_str().map(String::from))
+                .collect::<Option<Vec<_>>>()?
+                .join(",")
+                .parse()
+                .ok()?,
+            Some(_) => return None,
+            None => MountOptions::default(),
+        };
+        Some((source.into(), options))
+    }
+
+    fn value(&self) -> Value {
+        let source = Value::String(self.source.to_string_lossy().into_owned());
+        if self.options == MountOptions::default() {
+            return source;
+        }
+        let options = self.options.names().into_iter().map(Value::from).collect();
+        let entry = [
+            ("source".into(), source),
+            ("options".into(), Value::Array(options)),
+        ];
+        Value::Table(entry.into_iter().collect())
+    }
 }
 
 impl From<(&str, &str)> for MountInfo {
@@ -167,6 +501,149 @@ impl From<(&str, &str)> for MountInfo {
         MountInfo {
             source: info.0.into(),
             target: info.1.into(),
+            options: MountOptions::default(),
         }
     }
 }
+
+/// Parses `src:dst[:options]`, where the source is a path of the host
+fn parse_mount(mount: &str) -> Result<MountInfo, String> {
+    let mut parts = mount.splitn(3, ':');
+    let source = parts.next().unwrap_or_default();
+    let target = parts.next().ok_or("expected src:dst[:options]")?;
+    let options = parts.next().map(str::parse).transpose()?;
+    if !source.starts_with('/') || !target.starts_with('/') {
+        return Err("both paths need to be absolute".into());
+    }
+    Ok(MountInfo {
+        source: format!("{OLD_ROOT}{source}").into(),
+        target: target.into(),
+        options: options.unwrap_or_default(),
+    })
+}
+
+/// Options applied to a bind mount, with the syntax of mount(8)
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub struct MountOptions {
+    pub read_only: bool,
+    pub nosuid: bool,
+    pub noexec: bool,
+    /// Mounts and unmounts of the host below the mount are not propagated into the toolbox
+    pub private: bool,
+}
+
+impl MountOptions {
+    pub fn names(&self) -> Vec<&'static str> {
+        [
+            (self.read_only, "ro"),
+            (self.nosuid, "nosuid"),
+            (self.noexec, "noexec"),
+            (self.private, "private"),
+        ]
+        .into_iter()
+        .filter_map(|(set, name)| set.then_some(name))
+        .collect()
+    }
+}
+
+impl FromStr for MountOptions {
+    type Err = String;
+
+    fn from_str(options: &str) -> Result<Self, Self::Err> {
+        let mut parsed = MountOptions::default();
+        for option in options.split(',').filter(|o| !o.is_empty()) {
+            match option {
+                "ro" => parsed.read_only = true,
+                "rw" => parsed.read_only = false,
+                "nosuid" => parsed.nosuid = true,
+                "noexec" => parsed.noexec = true,
+                "private" => parsed.private = true,
+                _ => return Err(format!("unknown mount option {option}")),
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+/// Path of the global configuration, shared by every toolbox
+pub fn global_path() -> eyre::Result<PathBuf> {
+    let config = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => home()?.join(".config"),
+    };
+    Ok(config.join("unbox/config.toml"))
+}
+
+fn global_layer() -> eyre::Result<Option<(Origin, Table)>> {
+    let path = global_path()?;
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).wrap_err("Could not read the global config file"),
+    };
+    let table = toml::from_str(&content).wrap_err("Global config file is corrupted")?;
+    Ok(Some((Origin::Global(path), table)))
+}
+
+/// Merges the layers in order, tables like `mounts` are merged entry by entry
+fn merge(layers: Vec<(Origin, Table)>) -> (Table, BTreeMap<String, Origin>) {
+    let mut merged = Table::new();
+    let mut origins = BTreeMap::new();
+    for (origin, layer) in layers {
+        for (key, value) in layer {
+            match (merged.get_mut(&key), value) {
+                (Some(Value::Table(current)), Value::Table(entries)) => {
+                    for (inner, value) in entries {
+                        origins.insert(format!("{key}.{inner}"), origin.clone());
+                        current.insert(inner, value);
+                    }
+                }
+                (_, value) => {
+                    if let Value::Table(entries) = &value {
+                        for inner in entries.keys() {
+                            origins.insert(format!("{key}.{inner}"), origin.clone());
+                        }
+                    }
+                    origins.insert(key.clone(), origin.clone());
+                    merged.insert(key, value);
+                }
+            }
+        }
+    }
+    (merged, origins)
+}
+
+/// Values of the table that are not already the same in `beneath`, tables like `mounts` are
+/// compared entry by entry
+fn difference(table: Table, beneath: &Table) -> Table {
+    table
+        .into_iter()
+        .filter_map(|(key, value)| match (value, beneath.get(&key)) {
+            (Value::Table(entries), Some(Value::Table(beneath))) => {
+                let entries: Table = entries
+                    .into_iter()
+                    .filter(|(inner, value)| match beneath.get(inner) {
+                        Some(beneath) => value != beneath,
+                        // Disabling an entry only makes sense if a layer beneath has it
+                        None => value != &Value::Boolean(false),
+                    })
+                    .collect();
+                (!entries.is_empty()).then_some((key, Value::Table(entries)))
+            }
+            (value, Some(beneath)) if &value == beneath => None,
+            (value, _) => Some((key, value)),
+        })
+        .collect()
+}
+
+/// Quotes the key if it cannot be written as a bare TOML key
+fn bare(key: &str) -> String {
+    let is_bare = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    match is_bare {
+        true => key.to_string(),
+        false => Value::String(key.to_string()).to_string(),
+    }
+}
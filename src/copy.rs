@@ -0,0 +1,137 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use color_eyre::eyre;
+use color_eyre::eyre::WrapErr;
+use nix::sys::statfs::{statfs, BTRFS_SUPER_MAGIC};
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+
+use crate::namespaces::Mapper;
+
+/// Inode number of the root directory of every btrfs subvolume
+const SUBVOLUME_INODE: u64 = 256;
+
+/// Ways to copy a tree whose files are not shared with the source, from the cheapest
+pub const INDEPENDENT: [Method; 3] = [Method::Subvolume, Method::Reflink, Method::Copy];
+
+/// How a directory tree is copied, from the cheapest to the most expensive
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Method {
+    /// Snapshot of a btrfs subvolume, only possible if the source is one
+    Subvolume,
+    /// Copy whose files share their data with the source until one of them changes
+    Reflink,
+    /// Directories are copied and files are hardlinked, so both trees share the same files
+    Hardlink,
+    /// Full copy of every file
+    Copy,
+}
+
+impl std::fmt::Display for Method {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Method::Subvolume => write!(f, "btrfs snapshot"),
+            Method::Reflink => write!(f, "reflink"),
+            Method::Hardlink => write!(f, "hardlink"),
+            Method::Copy => write!(f, "copy"),
+        }
+    }
+}
+
+/// Copies `src` to `dst`, which must not exist, with the first of the methods that works. Owners,
+/// permissions, timestamps, xattrs and hardlinks are kept, as long as the ids of the owners are
+/// mapped in the current user namespace
+pub fn copy_tree(src: &Path, dst: &Path, methods: &[Method]) -> eyre::Result<Method> {
+    eyre::ensure!(!dst.exists(), "The directory {dst:?} already exists");
+    for &method in methods {
+        if method == Method::Subvolume && !is_subvolume(src) {
+            continue;
+        }
+        if copy_with(method, src, dst)? {
+            return Ok(method);
+        }
+        // A failed copy can leave some files behind
+        if dst.exists() {
+            remove_tree(dst)?;
+        }
+    }
+    Err(eyre::eyre!("Could not copy {src:?} to {dst:?}"))
+}
+
+/// Removes a tree made by `copy_tree`, which can be a btrfs subvolume
+pub fn remove_tree(path: &Path) -> eyre::Result<()> {
+    let mut delete = Command::new("btrfs");
+    delete.args(["subvolume", "delete"]).arg(path);
+    if is_subvolume(path) && succeeds(&mut delete)? {
+        return Ok(());
+    }
+    let removed = std::fs::remove_dir_all(path);
+    if removed.is_err() && Mapper::detect() == Mapper::Direct {
+        if let Some(file) = unmapped_file(path) {
+            eyre::bail!(
+                "Could not remove {path:?}, {file:?} is owned by a subordinate id that can only be \
+                mapped with newuidmap and newgidmap. Install them or remove it as root"
+            );
+        }
+    }
+    removed.wrap_err(format!("Could not remove {path:?}"))
+}
+
+/// First file of the tree owned by an id that is not mapped in the current user namespace
+fn unmapped_file(path: &Path) -> Option<PathBuf> {
+    let uid = users::get_current_uid();
+    WalkDir::new(path)
+        .into_iter()
+        .filter_map(Result::ok)
+        .find(|entry| entry.metadata().is_ok_and(|meta| meta.uid() != uid))
+        .map(|entry| entry.into_path())
+}
+
+fn is_subvolume(path: &Path) -> bool {
+    let btrfs = statfs(path).is_ok_and(|fs| fs.filesystem_type() == BTRFS_SUPER_MAGIC);
+    btrfs && std::fs::symlink_metadata(path).is_ok_and(|m| m.ino() == SUBVOLUME_INODE)
+}
+
+fn copy_with(method: Method, src: &Path, dst: &Path) -> eyre::Result<bool> {
+    let mut command = match method {
+        Method::Subvolume => {
+            let mut btrfs = Command::new("btrfs");
+            btrfs.args(["subvolume", "snapshot"]);
+            btrfs
+        }
+        Method::Reflink => {
+            let mut cp = Command::new("cp");
+            cp.args(["-a", "--reflink=always"]);
+            cp
+        }
+        Method::Hardlink => {
+            let mut cp = Command::new("cp");
+            cp.arg("-al");
+            cp
+        }
+        Method::Copy => {
+            let mut cp = Command::new("cp");
+            cp.arg("-a");
+            cp
+        }
+    };
+    succeeds(command.arg(src).arg(dst))
+}
+
+/// Whether the command succeeded, its output is hidden because a failure means trying another way
+fn succeeds(command: &mut Command) -> eyre::Result<bool> {
+    let status = command.stdout(Stdio::null()).stderr(Stdio::null()).status();
+    match status {
+        Ok(status) => Ok(status.success()),
+        // A missing program is just one more way that does not work
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e).wrap_err(format!("Could not execute {:?}", command.get_program())),
+    }
+}
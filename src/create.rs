@@ -4,81 +4,170 @@
 
 use std::ffi::OsStr;
 use std::fmt::Display;
-use std::fs::File;
+use std::fs::{DirBuilder, File};
+use std::os::unix::fs::DirBuilderExt;
 use std::path::{Path, PathBuf};
 use std::process::Output;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use clap::{Args, ValueEnum};
+use clap::{ArgGroup, Args};
 use color_eyre::eyre;
 use color_eyre::eyre::WrapErr;
 use indicatif::ProgressBar;
 use nix::sched::CloneFlags;
 use std::fs::create_dir_all;
-use tar::Archive;
 
-use crate::config::Config;
-use crate::namespaces::{Mapping, Namespace};
+use crate::archive::unpack_tar;
+use crate::config::{meta_path, Config, Engine};
+use crate::copy::remove_tree;
+use crate::namespaces::{Mapper, Mapping, Namespace};
+use crate::oci::{self, Image};
+use crate::registry::{self, Reference};
+use crate::user::User;
 
 /// Create a toolbox rootfs from an image
 #[derive(Args, PartialEq, Eq, Debug)]
+#[clap(group(ArgGroup::new("source").required(true).args(["tar", "image", "oci_layout", "docker_archive"])))]
 pub struct Create {
     #[clap(value_parser)]
     /// Name of the toolbox
     pub name: String,
     #[clap(short, long, value_parser)]
-    /// Path to the tarball
+    /// Path to the tarball, optionally compressed with gzip, xz, zstd or bzip2
     pub tar: Option<PathBuf>,
     #[clap(short, long, value_parser)]
     /// Url of the OCI image
     pub image: Option<String>,
+    #[clap(long, value_parser)]
+    /// Path to an OCI image layout directory
+    pub oci_layout: Option<PathBuf>,
+    #[clap(long, value_parser)]
+    /// Path to an archive created by `docker save`
+    pub docker_archive: Option<PathBuf>,
     #[clap(short, long, value_parser)]
-    /// OCI engine to extract the rootfs
+    /// OCI engine to extract the rootfs, the image is pulled directly from the registry if not provided
     pub engine: Option<Engine>,
     #[clap(short, long, value_parser)]
     /// Default shell for the image to be created
     pub shell: Option<String>,
+    #[clap(long, value_parser)]
+    /// Directory for the root filesystem instead of the storage, it must not exist or be empty
+    pub path: Option<PathBuf>,
+    #[clap(long, value_parser)]
+    /// Add the user to the wheel or sudo group of the image
+    pub sudo: bool,
     #[clap(short, long, value_parser)]
     /// Default shell for the image to be created
     pub quiet: bool,
 }
 
-/// OCI engine to extract the rootfs (docker or podman)
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, ValueEnum)]
-pub enum Engine {
-    Docker,
-    Podman,
-}
-
 pub fn create(args: Create) -> eyre::Result<()> {
     let mut config = Config::new(&args.name)?;
+    eyre::ensure!(
+        !meta_path(&args.name)?.exists(),
+        "There is already a toolbox with that name"
+    );
+    if let Some(path) = &args.path {
+        let path = std::path::absolute(path).wrap_err("Could not find the directory")?;
+        let empty = std::fs::read_dir(&path).is_ok_and(|mut d| d.next().is_none());
+        eyre::ensure!(
+            !path.exists() || empty,
+            "The directory {path:?} is not empty"
+        );
+        config.image = path.to_string_lossy().into_owned();
+    }
     let new_root = &config.image;
     eyre::ensure!(
-        !Path::new(new_root).exists(),
+        args.path.is_some() || !Path::new(new_root).exists(),
         "There is already an image with that name"
     );
+    let existed = Path::new(new_root).exists();
     create_dir_all(new_root).wrap_err("Could not create the new root directory")?;
-    if let Some(sh) = args.shell {
-        config.shell = sh;
+    if let Some(sh) = &args.shell {
+        config.shell = sh.clone();
+    }
+    config.created = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_secs());
+    config.source = source(&args)?;
+    config.engine = args.engine;
+    // Warned once here instead of on every run, `inspect` shows the mappings that are used
+    if Mapper::detect() == Mapper::Direct {
+        eprintln!("Warning: newuidmap and newgidmap could not be found, only your own user and group are mapped inside of the toolbox");
+        eprintln!("Warning: changing the owner of files or switching users will fail inside of the toolbox");
     }
-    config.write(&args.name)?;
+    let user = User::current(&config)?;
+    let setup = Setup {
+        user: &user,
+        sudo: args.sudo,
+        quiet: args.quiet,
+    };
 
-    if let Some(tar) = args.tar {
-        setup_new_root(new_root, tar, args.quiet)
-    } else if let Some(oci) = args.image {
+    // The meta file is only written once the image is ready, so that a failure leaves nothing behind
+    config.digest = match new_root_from(&args, new_root, setup) {
+        Ok(digest) => digest,
+        Err(error) => {
+            // The error is ignored because the toolbox could not be created anyway
+            let _ = remove_tree(Path::new(new_root));
+            if existed {
+                let _ = create_dir_all(new_root);
+            }
+            return Err(error);
+        }
+    };
+    config.write(&args.name)
+}
+
+/// Where the files of the toolbox come from, as it is recorded in the meta file
+fn source(args: &Create) -> eyre::Result<Option<String>> {
+    let path = [&args.tar, &args.oci_layout, &args.docker_archive]
+        .into_iter()
+        .flatten()
+        .next();
+    if let Some(path) = path {
+        let path = std::path::absolute(path).wrap_err("Could not find the source of the image")?;
+        return Ok(Some(path.to_string_lossy().into_owned()));
+    }
+    match (&args.image, args.engine) {
+        (Some(image), None) => Ok(Some(image.parse::<Reference>()?.to_string())),
+        (image, _) => Ok(image.clone()),
+    }
+}
+
+/// Sets up the new root from the source given in the arguments, returning the digest of the image
+fn new_root_from(args: &Create, new_root: &str, setup: Setup<'_>) -> eyre::Result<Option<String>> {
+    if let Some(tar) = args.tar.clone() {
+        setup_new_root(new_root, Source::Tar(tar), setup)
+    } else if let Some(oci) = &args.image {
+        let tmp = private_dir(&args.name)?;
         // podman export $(podman create alpine) --output=alpine.tar
-        let tar_file = format!("/tmp/unbox-{}-image.tar", args.name);
-        match args
-            .engine
-            .ok_or_else(|| eyre::eyre!("A valid engine has not been provided"))?
-        {
-            Engine::Docker => get_image("docker", &oci, &tar_file, args.quiet)?,
-            Engine::Podman => get_image("podman", &oci, &tar_file, args.quiet)?,
+        let tar_file = tmp.join("image.tar").to_string_lossy().into_owned();
+        let source = match args.engine {
+            Some(Engine::Docker) => get_image("docker", oci, &tar_file, args.quiet)
+                .map(|()| Source::Tar(tar_file.into())),
+            Some(Engine::Podman) => get_image("podman", oci, &tar_file, args.quiet)
+                .map(|()| Source::Tar(tar_file.into())),
+            None => oci
+                .parse()
+                .map(|reference| Source::Registry(reference, tmp.clone())),
         };
-        setup_new_root(new_root, tar_file.into(), args.quiet)
+        let created = source.and_then(|source| setup_new_root(new_root, source, setup));
+        // The error is ignored because the files are only a temporary copy
+        let _ = std::fs::remove_dir_all(&tmp);
+        created
+    } else if let Some(layout) = args.oci_layout.clone() {
+        setup_new_root(new_root, Source::Layout(layout), setup)
+    } else if let Some(archive) = args.docker_archive.clone() {
+        let extracted = private_dir(&args.name)?;
+        let source = Source::DockerArchive(archive, extracted.clone());
+        let created = setup_new_root(new_root, source, setup);
+        // The error is ignored because the files are only a temporary copy
+        let _ = std::fs::remove_dir_all(&extracted);
+        created
     } else {
         Err(eyre::eyre!(
-            "No tar archive or valid OCI arguments have been provided"
+            "No tar archive, OCI image or docker archive has been provided"
         ))
     }
 }
@@ -115,45 +204,98 @@ impl Spinner {
     }
 }
 
-fn setup_new_root(new_root: &str, tar: PathBuf, quiet: bool) -> eyre::Result<()> {
+/// Origin of the files of the new root filesystem
+enum Source {
+    Tar(PathBuf),
+    /// Image in an OCI registry and the directory where its layers are downloaded
+    Registry(Reference, PathBuf),
+    Layout(PathBuf),
+    /// Archive created by `docker save` and the directory where it is extracted
+    DockerArchive(PathBuf, PathBuf),
+}
+
+/// Options to finish the setup of the new root filesystem
+struct Setup<'a> {
+    user: &'a User,
+    sudo: bool,
+    quiet: bool,
+}
+
+fn setup_new_root(
+    new_root: &str,
+    source: Source,
+    setup: Setup<'_>,
+) -> eyre::Result<Option<String>> {
     let flags = CloneFlags::CLONE_NEWUSER;
-    let uid = users::get_current_uid().to_string();
-    let gid = users::get_current_gid().to_string();
-    let mut ns = Namespace::start(flags, &[id_map(&uid)], &[id_map(&gid)])?;
+    let uid = users::get_current_uid();
+    let gid = users::get_current_gid();
+    let mut ns = Namespace::start(flags, &[id_map(uid)], &[id_map(gid)])?;
     ns.wait();
-    let spinner = Spinner::new(quiet);
-    spinner.message("Unpacking tar file");
-    unpack_tar(tar, new_root)?;
+    let spinner = Spinner::new(setup.quiet);
+    let digest = match source {
+        Source::Tar(tar) => {
+            spinner.message("Unpacking tar file");
+            unpack_tar(tar, new_root, false)?;
+            None
+        }
+        Source::Registry(reference, layers) => {
+            spinner.message("Downloading image");
+            let image = registry::pull(&reference, &layers);
+            unpack_temporary(image, new_root, &layers, &spinner)?
+        }
+        Source::Layout(layout) => {
+            spinner.message("Unpacking image layers");
+            let image = oci::from_layout(&layout)?;
+            image.unpack(Path::new(new_root))?;
+            image.digest
+        }
+        Source::DockerArchive(archive, extracted) => {
+            spinner.message("Extracting docker archive");
+            let image = oci::from_docker_archive(&archive, &extracted);
+            unpack_temporary(image, new_root, &extracted, &spinner)?
+        }
+    };
     spinner.message("Setting up files and directories");
     let dirs = ["host", "proc", "sys", "dev"];
     create_dirs(new_root, &dirs)?;
     File::create(format!("{new_root}/etc/resolv.conf")).expect("path exists and is writable");
-    // TODO: create user
+    spinner.message("Creating user");
+    setup.user.create(Path::new(new_root), setup.sudo)?;
     spinner.clear();
-    Ok(())
+    Ok(digest)
 }
 
-fn unpack_tar(tar: PathBuf, new_root: &str) -> eyre::Result<()> {
-    let archive = File::open(tar).wrap_err("Could not open the tar file")?;
-    let mut tar = Archive::new(archive);
-    let mut dirs = Vec::new();
-    for entry in tar.entries()? {
-        let mut entry = entry?;
-        let path = entry.path()?;
-        if path.is_dir() {
-            dirs.push(entry);
-        } else {
-            entry
-                .unpack_in(new_root)
-                .wrap_err("Could not unpack entry")?;
-        }
-    }
-    dirs.sort_unstable_by_key(|b| std::cmp::Reverse(b.path_bytes().len()));
-    for mut dir in dirs {
-        dir.unpack_in(new_root)
-            .wrap_err("Could not unpack a directory")?;
-    }
-    Ok(())
+/// Unpacks an image whose layers are stored in a temporary directory, returning its digest
+fn unpack_temporary(
+    image: eyre::Result<Image>,
+    new_root: &str,
+    tmp: &Path,
+    spinner: &Spinner,
+) -> eyre::Result<Option<String>> {
+    let unpacked = image.and_then(|image| {
+        spinner.message("Unpacking image layers");
+        image.unpack(Path::new(new_root))?;
+        Ok(image.digest)
+    });
+    // The error is ignored because the layers are only a temporary copy
+    let _ = std::fs::remove_dir_all(tmp);
+    unpacked
+}
+
+/// Creates a new directory for temporary files that only the user can access. It must not exist
+/// yet, otherwise another user could have prepared it to swap the files after they are verified
+fn private_dir(name: &str) -> eyre::Result<PathBuf> {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or_default();
+    let unique = format!("unbox-{name}-{}-{nanos:x}", std::process::id());
+    let dir = std::env::temp_dir().join(unique);
+    DirBuilder::new()
+        .mode(0o700)
+        .create(&dir)
+        .wrap_err("Could not create a temporary directory")?;
+    Ok(dir)
 }
 
 fn get_image(engine: &str, url: &str, tar_file: &str, quiet: bool) -> eyre::Result<()> {
@@ -188,10 +330,10 @@ fn create_dirs(root: &str, dirs: &[&str]) -> eyre::Result<()> {
     Ok(())
 }
 
-fn id_map(guid: &str) -> Mapping<'_> {
+fn id_map(guid: u32) -> Mapping {
     Mapping {
-        inside: "0",
+        inside: 0,
         outside: guid,
-        len: "1",
+        len: 1,
     }
 }
@@ -0,0 +1,147 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::path::PathBuf;
+
+use clap::Args;
+use color_eyre::eyre;
+use color_eyre::eyre::WrapErr;
+
+use crate::config::Config;
+
+/// PATH inside of the toolbox when the one of the host is not kept
+const DEFAULT_PATH: &str = "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+
+/// Variables of the host kept with `clean_env` unless the toolbox configures its own list
+pub const DEFAULT_ALLOW: [&str; 9] = [
+    "TERM",
+    "COLORTERM",
+    "LANG",
+    "LC_*",
+    "USER",
+    "LOGNAME",
+    "DISPLAY",
+    "WAYLAND_DISPLAY",
+    "XDG_RUNTIME_DIR",
+];
+
+/// Variables of the host that break the tools of the toolbox unless it configures its own list
+pub const DEFAULT_DENY: [&str; 4] = [
+    "LD_LIBRARY_PATH",
+    "LD_PRELOAD",
+    "PYTHONPATH",
+    "XDG_DATA_DIRS",
+];
+
+/// Environment variables for a single run, on top of the configuration of the toolbox
+#[derive(Args, Clone, Default, PartialEq, Eq, Debug)]
+pub struct Variables {
+    #[clap(long, value_parser)]
+    /// Only keep the variables of the host in the allow list of the toolbox
+    pub clean_env: bool,
+    #[clap(short, long, value_parser = parse_variable)]
+    /// Set a variable as KEY=VAL, or KEY to take its value from the host
+    pub env: Vec<(String, Option<String>)>,
+    #[clap(long, value_parser)]
+    /// Read variables from a file with a KEY=VAL on each line
+    pub env_file: Vec<PathBuf>,
+}
+
+/// Parses `KEY=VAL`, or a bare `KEY` whose value is taken from the host
+pub fn parse_variable(variable: &str) -> Result<(String, Option<String>), String> {
+    let (key, value) = match variable.split_once('=') {
+        Some((key, value)) => (key, Some(value.to_string())),
+        None => (variable, None),
+    };
+    if key.is_empty() || key.contains('\0') {
+        return Err("expected KEY=VAL or KEY".into());
+    }
+    Ok((key.to_string(), value))
+}
+
+/// Replaces the environment of the current process with the one the toolbox should see
+pub fn apply(config: &Config, variables: &Variables) -> eyre::Result<()> {
+    let environment = environment(config, variables)?;
+    for (key, _) in env::vars_os() {
+        if !environment.contains_key(&key) {
+            env::remove_var(key);
+        }
+    }
+    for (key, value) in environment {
+        env::set_var(key, value);
+    }
+    Ok(())
+}
+
+fn environment(
+    config: &Config,
+    variables: &Variables,
+) -> eyre::Result<BTreeMap<OsString, OsString>> {
+    let clean = variables.clean_env || config.clean_env;
+    let mut environment: BTreeMap<_, _> = env::vars_os()
+        .filter(|(key, _)| {
+            let key = key.to_string_lossy();
+            match clean {
+                true => matches(&config.env_allow, &key),
+                false => !matches(&config.env_deny, &key),
+            }
+        })
+        .collect();
+
+    let path = match environment.remove(OsStr::new("PATH")) {
+        Some(mut path) => {
+            path.push(":/bin");
+            path.push(":/sbin");
+            path
+        }
+        None => DEFAULT_PATH.into(),
+    };
+    environment.insert("PATH".into(), path);
+    environment.insert("HOME".into(), config.home.clone().into());
+
+    for (key, value) in &config.env {
+        environment.insert(key.into(), value.into());
+    }
+    for file in &variables.env_file {
+        let content = std::fs::read_to_string(file)
+            .wrap_err(format!("Could not read the environment file {file:?}"))?;
+        let lines = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+        for line in lines {
+            let variable = parse_variable(line)
+                .map_err(|e| eyre::eyre!("Invalid line {line:?} in {file:?}: {e}"))?;
+            set(&mut environment, variable);
+        }
+    }
+    for variable in &variables.env {
+        set(&mut environment, variable.clone());
+    }
+    Ok(environment)
+}
+
+fn set(environment: &mut BTreeMap<OsString, OsString>, (key, value): (String, Option<String>)) {
+    let value = match value {
+        Some(value) => Some(value.into()),
+        None => env::var_os(&key),
+    };
+    // Like the shell, a variable that the host does not have is left unset
+    if let Some(value) = value {
+        environment.insert(key.into(), value);
+    }
+}
+
+/// Whether a name is in a list of names, where a trailing `*` matches any suffix
+fn matches(patterns: &[String], name: &str) -> bool {
+    patterns
+        .iter()
+        .any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            None => name == pattern,
+        })
+}
@@ -0,0 +1,194 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use clap::Args;
+use color_eyre::eyre;
+use color_eyre::eyre::WrapErr;
+use tar::{Builder, EntryType, Header, HeaderMode};
+use toml::value::Table;
+use walkdir::WalkDir;
+
+use crate::archive::{unpack_tar, META_ENTRY};
+use crate::compression::{Compression, Encoder};
+use crate::config::{meta_path, Config};
+use crate::copy::remove_tree;
+use crate::namespaces::{enter_user_namespace, Mapper};
+use crate::session::Session;
+
+/// Export a toolbox to an archive that can be imported somewhere else
+#[derive(Args, PartialEq, Eq, Debug)]
+pub struct Export {
+    #[clap(value_parser)]
+    /// Name of the toolbox
+    pub name: String,
+    #[clap(short, long, value_parser)]
+    /// Path of the archive, compressed with gzip, xz, zstd or bzip2 depending on its extension
+    pub output: PathBuf,
+}
+
+/// Create a toolbox from an archive made by `export`
+#[derive(Args, PartialEq, Eq, Debug)]
+pub struct Import {
+    #[clap(value_parser)]
+    /// Path of the archive
+    pub file: PathBuf,
+    #[clap(value_parser)]
+    /// Name of the new toolbox, the name of the file without its extensions by default
+    pub name: Option<String>,
+}
+
+pub fn export(args: Export) -> eyre::Result<()> {
+    let config = Config::find(&args.name)?;
+    let image = Path::new(&config.image);
+    eyre::ensure!(image.is_dir(), "The image of {} does not exist", args.name);
+    // Files being written by the toolbox would end up half written in the archive
+    eyre::ensure!(
+        Session::live(&args.name)?.is_empty(),
+        "The toolbox {0} is still running, stop it with `unbox stop {0}` first",
+        args.name
+    );
+    let meta = match meta_path(&args.name)? {
+        path if path.exists() => {
+            std::fs::read_to_string(path).wrap_err("Could not read meta file")?
+        }
+        _ => String::new(),
+    };
+    let file = File::create(&args.output).wrap_err("Could not create the archive")?;
+    let compression = Compression::from_extension(&args.output);
+    let encoder = Encoder::new(BufWriter::new(file), compression)?;
+    // The files are read with the ids they have inside of the toolbox, not the subordinate ones
+    enter_user_namespace()?;
+    let mut builder = Builder::new(encoder);
+    builder.follow_symlinks(false);
+    append_meta(&mut builder, &meta)?;
+    append_image(&mut builder, image)?;
+    builder
+        .into_inner()
+        .and_then(Encoder::finish)
+        .and_then(|mut file| file.flush())
+        .wrap_err("Could not write the archive")
+}
+
+pub fn import(args: Import) -> eyre::Result<()> {
+    let name = match args.name {
+        Some(name) => name,
+        None => default_name(&args.file)?,
+    };
+    let config = Config::new(&name)?;
+    eyre::ensure!(
+        !meta_path(&name)?.exists(),
+        "There is already a toolbox with that name"
+    );
+    eyre::ensure!(
+        !Path::new(&config.image).exists(),
+        "There is already an image with that name"
+    );
+    // Without subordinate ids only the files of the user could be created
+    let (subuids, _) = Mapper::detect().ranges()?;
+    let ownership = users::get_current_uid() == 0 || !subuids.is_empty();
+    if !ownership {
+        eprintln!("Warning: there are no subordinate ids to map, the user will own every file");
+    }
+    enter_user_namespace()?;
+    std::fs::create_dir_all(&config.image).wrap_err("Could not create the new root directory")?;
+    let imported = unpack_meta(args.file, &config.image, ownership)
+        .and_then(|meta| {
+            std::fs::write(meta_path(&name)?, meta).wrap_err("Could not create meta file")
+        })
+        .and_then(|()| Config::read(&name)?.write(&name));
+    if imported.is_err() {
+        // A half unpacked image would block importing again with the same name
+        let _ = remove_tree(Path::new(&config.image));
+        let _ = std::fs::remove_file(meta_path(&name)?);
+    }
+    imported
+}
+
+/// Unpacks the archive into the new root and returns the meta file that it had
+fn unpack_meta(file: PathBuf, new_root: &str, ownership: bool) -> eyre::Result<String> {
+    let mut meta: Table = match unpack_tar(file, new_root, ownership)? {
+        Some(meta) => {
+            toml::from_str(&meta).wrap_err("The configuration in the archive is corrupted")?
+        }
+        None => Table::new(),
+    };
+    // The root filesystem of the new toolbox is always in the storage
+    meta.remove("image");
+    Ok(toml::to_string(&meta).expect("valid toml config"))
+}
+
+/// Name of the file without the extensions, like `alpine` for `alpine.tar.zst`
+fn default_name(file: &Path) -> eyre::Result<String> {
+    let name = file
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.split('.').next())
+        .filter(|name| !name.is_empty());
+    match name {
+        Some(name) => Ok(name.to_string()),
+        None => Err(eyre::eyre!(
+            "Could not find a name for the toolbox in {file:?}"
+        )),
+    }
+}
+
+fn append_meta<W: Write>(builder: &mut Builder<W>, meta: &str) -> eyre::Result<()> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let mut header = Header::new_gnu();
+    header.set_size(meta.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(now);
+    header.set_cksum();
+    builder
+        .append_data(&mut header, META_ENTRY, meta.as_bytes())
+        .wrap_err("Could not write the configuration to the archive")
+}
+
+/// Appends every file of the image, with the later paths of a hardlinked file as links to the first
+fn append_image<W: Write>(builder: &mut Builder<W>, image: &Path) -> eyre::Result<()> {
+    let mut links = HashMap::new();
+    let entries = WalkDir::new(image)
+        .min_depth(1)
+        .same_file_system(true)
+        .sort_by_file_name();
+    for entry in entries {
+        let entry = entry.wrap_err("Could not read the image of the toolbox")?;
+        let path = entry.path();
+        let name = path
+            .strip_prefix(image)
+            .expect("entries are inside the image");
+        let meta = entry.metadata()?;
+        // Sockets only make sense for the process listening on them
+        if meta.file_type().is_socket() {
+            continue;
+        }
+        if meta.nlink() > 1 && !meta.is_dir() {
+            if let Some(first) = links.get(&(meta.dev(), meta.ino())) {
+                let mut header = Header::new_gnu();
+                header.set_metadata_in_mode(&meta, HeaderMode::Complete);
+                header.set_entry_type(EntryType::Link);
+                header.set_size(0);
+                builder
+                    .append_link(&mut header, name, first)
+                    .wrap_err(format!("Could not add {name:?} to the archive"))?;
+                continue;
+            }
+            links.insert((meta.dev(), meta.ino()), name.to_path_buf());
+        }
+        builder
+            .append_path_with_name(path, name)
+            .wrap_err(format!("Could not add {name:?} to the archive"))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,277 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::ffi::{OsStr, OsString};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::io::RawFd;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Child, Command};
+
+use clap::Args;
+use color_eyre::eyre;
+use color_eyre::eyre::WrapErr;
+use nix::errno::Errno;
+use nix::fcntl::{fcntl, FcntlArg, FdFlag};
+use nix::mount::{mount, MsFlags};
+use nix::sys::signal::{kill, Signal};
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::{close, pipe, read, write, Pid};
+use signal_hook::iterator::Signals;
+
+use crate::namespaces::{ExecError, Identity};
+use crate::run::EXIT_FAILURE;
+
+/// Signals that the init process passes on to the command
+pub const FORWARDED: [Signal; 3] = [Signal::SIGINT, Signal::SIGTERM, Signal::SIGWINCH];
+
+/// How the command of a toolbox finished
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Exit {
+    Code(i32),
+    Signal(Signal),
+}
+
+impl Exit {
+    /// Exit code that shells report for it, `128 + signal` if the command was killed
+    pub fn code(self) -> i32 {
+        match self {
+            Exit::Code(code) => code,
+            Exit::Signal(signal) => 128 + signal as i32,
+        }
+    }
+
+    /// Exits like the command did, so a command killed by a signal also kills the current process
+    pub fn exit(self) -> ! {
+        if let Exit::Signal(signal) = self {
+            let _ = signal_hook::low_level::emulate_default_handler(signal as i32);
+        }
+        std::process::exit(self.code())
+    }
+}
+
+// Run as the first process of a new PID namespace
+/// Internal subcommand. Should not be used directly
+#[derive(Args, PartialEq, Eq, Debug)]
+pub struct Init {
+    #[clap(long, value_parser)]
+    ready: RawFd,
+    #[clap(long, value_parser)]
+    sync: RawFd,
+    #[clap(long, value_parser)]
+    uid: u32,
+    #[clap(long, value_parser)]
+    gid: u32,
+    #[clap(value_parser)]
+    cmd: OsString,
+    #[clap(value_parser)]
+    args: Vec<OsString>,
+}
+
+pub fn init(args: Init) -> eyre::Result<()> {
+    // Every library is loaded by now, so the parent can pivot into the toolbox
+    let _ = write(args.ready, &[0]);
+    // The pipe stays open to report the signal that kills the command, but not in the command
+    fcntl(args.ready, FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC))
+        .wrap_err("Could not keep the pipe of the init process")?;
+    // Without a working directory the parent failed to set up the toolbox and already reported it
+    let workdir = match wait_for_setup(args.sync)? {
+        Some(workdir) => workdir,
+        None => std::process::exit(EXIT_FAILURE),
+    };
+    std::env::set_current_dir(&workdir).wrap_err("Could not change the working directory")?;
+    std::env::set_var("PWD", &workdir);
+    let flags = MsFlags::MS_NOSUID | MsFlags::MS_NODEV | MsFlags::MS_NOEXEC;
+    // The /proc of the host shows the processes of the host, only a new one is scoped to the namespace
+    mount(Some("proc"), "/proc", Some("proc"), flags, None::<&str>)
+        .wrap_err("Could not mount /proc for the PID namespace")?;
+    let identity = Identity {
+        uid: args.uid,
+        gid: args.gid,
+    };
+    let exit = spawn_supervised(&args.cmd, &args.args, identity, &FORWARDED)?;
+    // The first process of a PID namespace cannot be killed by the signals sent from inside of it,
+    // so the parent is told about the signal to die with it instead
+    if let Exit::Signal(signal) = exit {
+        let _ = write(args.ready, &[signal as u8]);
+    }
+    std::process::exit(exit.code())
+}
+
+/// Runs the command as a child and waits for it, which is needed when the current process cannot
+/// become the command, e.g. after joining a PID namespace only its children are inside of it
+pub fn spawn_and_wait<C, A>(cmd: C, args: &[A], identity: Identity) -> eyre::Result<Exit>
+where
+    C: AsRef<OsStr>,
+    A: AsRef<OsStr>,
+{
+    // Interrupts from the terminal already reach the child, as it is in the same process group
+    spawn_supervised(cmd, args, identity, &[Signal::SIGTERM])
+}
+
+fn spawn_supervised<C, A>(
+    cmd: C,
+    args: &[A],
+    identity: Identity,
+    forward: &[Signal],
+) -> eyre::Result<Exit>
+where
+    C: AsRef<OsStr>,
+    A: AsRef<OsStr>,
+{
+    let signals = catch_signals()?;
+    let child = Command::new(&cmd)
+        .args(args)
+        .uid(identity.uid)
+        .gid(identity.gid)
+        .spawn()
+        .map_err(|source| ExecError {
+            command: cmd.as_ref().to_os_string(),
+            source,
+        })?;
+    supervise(signals, child.id(), forward)
+}
+
+fn wait_for_setup(sync: RawFd) -> eyre::Result<Option<OsString>> {
+    let mut workdir = Vec::new();
+    let mut buffer = [0; 4096];
+    loop {
+        match read(sync, &mut buffer) {
+            Ok(0) => break,
+            Ok(n) => workdir.extend_from_slice(&buffer[..n]),
+            Err(Errno::EINTR) => continue,
+            Err(e) => return Err(e).wrap_err("Could not wait for the toolbox to be set up"),
+        }
+    }
+    let _ = close(sync);
+    Ok((!workdir.is_empty()).then(|| OsString::from_vec(workdir)))
+}
+
+/// Init process that waits for the toolbox to be set up before running the command
+pub struct InitProcess {
+    child: Child,
+    sync: RawFd,
+    /// Where the init process reports the signal that killed the command
+    ready: RawFd,
+    signals: Signals,
+}
+
+impl InitProcess {
+    /// Spawns the init process, which has to happen before pivoting because the binary of unbox
+    /// may not be able to run with the libraries of the toolbox. The pivot also moves its root
+    pub fn spawn<C, A>(cmd: C, args: &[A], identity: Identity) -> eyre::Result<Self>
+    where
+        C: AsRef<OsStr>,
+        A: AsRef<OsStr>,
+    {
+        let (receiver, sender) = channel()?;
+        let (ready_receiver, ready_sender) = channel()?;
+        // Only the ends used by the init process are inherited by it
+        for fd in [sender, ready_receiver] {
+            fcntl(fd, FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC))
+                .wrap_err("Could not create pipe for the init process")?;
+        }
+        let signals = catch_signals()?;
+        let child = Command::new("/proc/self/exe")
+            .arg("init")
+            .args(["--ready", &ready_sender.to_string()])
+            .args(["--sync", &receiver.to_string()])
+            .args(["--uid", &identity.uid.to_string()])
+            .args(["--gid", &identity.gid.to_string()])
+            .arg("--")
+            .arg(cmd)
+            .args(args)
+            .spawn()
+            .wrap_err("Could not spawn the init process")?;
+        let _ = close(receiver);
+        let _ = close(ready_sender);
+        let ready = loop {
+            match read(ready_receiver, &mut [0]) {
+                Err(Errno::EINTR) => continue,
+                result => break result,
+            }
+        };
+        eyre::ensure!(ready == Ok(1), "The init process failed to start");
+        Ok(InitProcess {
+            child,
+            sync: sender,
+            ready: ready_receiver,
+            signals,
+        })
+    }
+
+    /// Lets the init process run the command from `workdir` and waits for it to finish
+    pub fn start(self, workdir: &Path) -> eyre::Result<Exit> {
+        let mut workdir = workdir.as_os_str().as_bytes();
+        while !workdir.is_empty() {
+            match write(self.sync, workdir) {
+                Ok(n) => workdir = &workdir[n..],
+                Err(Errno::EINTR) => continue,
+                Err(e) => return Err(e).wrap_err("Could not start the init process"),
+            }
+        }
+        let _ = close(self.sync);
+        // Interrupts from the terminal already reach init, as it is in the same process group
+        let exit = supervise(self.signals, self.child.id(), &[Signal::SIGTERM])?;
+        let mut signal = [0];
+        let reported = matches!(read(self.ready, &mut signal), Ok(1));
+        let _ = close(self.ready);
+        match exit {
+            Exit::Code(_) if reported => Signal::try_from(i32::from(signal[0]))
+                .map(Exit::Signal)
+                .wrap_err("The init process reported an unknown signal"),
+            exit => Ok(exit),
+        }
+    }
+}
+
+fn channel() -> eyre::Result<(RawFd, RawFd)> {
+    pipe().wrap_err("Could not create pipe for the init process")
+}
+
+/// Starts catching the signals handled by `supervise`, it has to be called before spawning the
+/// child so that its exit is not missed. Handlers are reset when the child executes its command
+fn catch_signals() -> eyre::Result<Signals> {
+    let mut signals: Vec<_> = FORWARDED.iter().map(|&s| s as i32).collect();
+    signals.push(Signal::SIGCHLD as i32);
+    Signals::new(signals).wrap_err("Could not catch signals")
+}
+
+/// Waits for the child to exit while passing on the `forward` signals and reaping any other
+/// process, returning how the child finished
+fn supervise(mut signals: Signals, child: u32, forward: &[Signal]) -> eyre::Result<Exit> {
+    let child = Pid::from_raw(child as i32);
+    // The child may have exited before any signal was caught
+    if let Some(exit) = reap(child)? {
+        return Ok(exit);
+    }
+    for signal in signals.forever() {
+        let signal = Signal::try_from(signal).wrap_err("Caught an unknown signal")?;
+        if signal != Signal::SIGCHLD {
+            if forward.contains(&signal) {
+                let _ = kill(child, signal);
+            }
+            continue;
+        }
+        if let Some(exit) = reap(child)? {
+            return Ok(exit);
+        }
+    }
+    eyre::bail!("Stopped catching signals before the child exited")
+}
+
+fn reap(child: Pid) -> eyre::Result<Option<Exit>> {
+    let mut exit = None;
+    loop {
+        match waitpid(None, Some(WaitPidFlag::WNOHANG)) {
+            Ok(WaitStatus::Exited(pid, status)) if pid == child => exit = Some(Exit::Code(status)),
+            Ok(WaitStatus::Signaled(pid, signal, _)) if pid == child => {
+                exit = Some(Exit::Signal(signal))
+            }
+            Ok(WaitStatus::StillAlive) | Err(Errno::ECHILD) => return Ok(exit),
+            Ok(_) => continue,
+            Err(e) => return Err(e).wrap_err("Could not wait for child processes"),
+        }
+    }
+}
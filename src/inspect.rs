@@ -0,0 +1,226 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs::{read_link, symlink_metadata};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use clap::{Args, ValueEnum};
+use color_eyre::eyre;
+use color_eyre::eyre::WrapErr;
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::namespaces::{follow_symlink, Mapper, Mapping, OLD_ROOT};
+use crate::session::Session;
+use crate::usage::{Size, Usage};
+
+/// Show everything about a toolbox
+#[derive(Args, PartialEq, Eq, Debug)]
+pub struct Inspect {
+    #[clap(value_parser)]
+    /// Name of the toolbox
+    name: String,
+    #[clap(long, value_parser, default_value = "text")]
+    /// Format of the output
+    format: Format,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, ValueEnum)]
+enum Format {
+    Text,
+    Json,
+}
+
+#[derive(Serialize)]
+struct Inspection {
+    name: String,
+    config: Config,
+    mounts: Vec<Mount>,
+    uid_mappings: Vec<Mapping>,
+    gid_mappings: Vec<Mapping>,
+    usage: Usage,
+    /// Fields of the os-release file of the image
+    os_release: BTreeMap<String, String>,
+    sessions: Vec<Session>,
+}
+
+#[derive(Serialize)]
+struct Mount {
+    target: String,
+    source: String,
+    /// Path of the host that ends up mounted, after following a symlink in the source
+    resolved: String,
+    options: Vec<&'static str>,
+}
+
+pub fn inspect(args: Inspect) -> eyre::Result<()> {
+    let config = Config::find(&args.name)?;
+    let image = PathBuf::from(&config.image);
+    let mounts = config
+        .mounts()
+        .map(|mount| {
+            let mount = mount?;
+            let source = mount.source.to_string_lossy().into_owned();
+            // Sources are paths as seen from inside of the toolbox, under the old root
+            let host = match source.strip_prefix(OLD_ROOT) {
+                Some(host) if host.starts_with('/') => host.to_string(),
+                _ => source.clone(),
+            };
+            Ok(Mount {
+                target: mount.target.to_string_lossy().into_owned(),
+                resolved: follow_symlink(host.into()).to_string_lossy().into_owned(),
+                source,
+                options: mount.options.names(),
+            })
+        })
+        .collect::<eyre::Result<_>>()?;
+    let uid = users::get_current_uid();
+    let gid = users::get_current_gid();
+    let (subuids, subgids) = Mapper::detect().ranges()?;
+    let inspection = Inspection {
+        name: args.name.clone(),
+        uid_mappings: config.userns.mappings(uid, &subuids),
+        gid_mappings: config.userns.mappings(gid, &subgids),
+        usage: Usage::of(&image),
+        os_release: os_release(&image),
+        sessions: Session::live(&args.name)?,
+        mounts,
+        config,
+    };
+    match args.format {
+        Format::Text => print!("{}", inspection.text()),
+        Format::Json => {
+            let json =
+                serde_json::to_string_pretty(&inspection).wrap_err("Could not format as JSON")?;
+            println!("{json}");
+        }
+    }
+    Ok(())
+}
+
+impl Inspection {
+    fn text(&self) -> String {
+        let config = &self.config;
+        let mut text = String::new();
+        let system = self
+            .os_release
+            .get("PRETTY_NAME")
+            .or(self.os_release.get("NAME"));
+        let created = config.created.map(|secs| {
+            humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(secs)).to_string()
+        });
+        let size = format!(
+            "{} ({} on disk)",
+            Size(self.usage.apparent),
+            Size(self.usage.real)
+        );
+        let mappings = |mappings: &[Mapping]| {
+            let mappings: Vec<_> = mappings
+                .iter()
+                .map(|m| match m.len {
+                    1 => format!("{} to {}", m.inside, m.outside),
+                    len => format!(
+                        "{}-{} to {}-{}",
+                        m.inside,
+                        m.inside + (len - 1),
+                        m.outside,
+                        m.outside + (len - 1)
+                    ),
+                })
+                .collect();
+            mappings.join(", ")
+        };
+        let fields = [
+            ("name", Some(self.name.clone())),
+            ("image", Some(config.image.clone())),
+            ("system", system.cloned()),
+            ("size", Some(size)),
+            ("created", created),
+            ("source", config.source.clone()),
+            ("digest", config.digest.clone()),
+            ("engine", config.engine.as_ref().map(value_name)),
+            ("shell", Some(config.shell.clone())),
+            ("hostname", Some(config.hostname.clone())),
+            ("home", Some(config.home.clone())),
+            ("userns", Some(value_name(&config.userns))),
+            ("uids", Some(mappings(&self.uid_mappings))),
+            ("gids", Some(mappings(&self.gid_mappings))),
+            ("pidns", Some(config.pidns.to_string())),
+            ("network", Some(value_name(&config.network))),
+            ("clean env", Some(config.clean_env.to_string())),
+        ];
+        for (key, value) in fields {
+            if let Some(value) = value {
+                let _ = writeln!(text, "{:<10} {value}", format!("{key}:"));
+            }
+        }
+        if !config.env.is_empty() {
+            let _ = writeln!(text, "\nenvironment:");
+            for (key, value) in &config.env {
+                let _ = writeln!(text, "  {key}={value}");
+            }
+        }
+        let _ = writeln!(text, "\nmounts:");
+        for mount in &self.mounts {
+            let _ = write!(text, "  {} from {}", mount.target, mount.resolved);
+            if !mount.options.is_empty() {
+                let _ = write!(text, " ({})", mount.options.join(","));
+            }
+            let _ = writeln!(text);
+        }
+        let _ = writeln!(text, "\nsessions:");
+        if self.sessions.is_empty() {
+            let _ = writeln!(text, "  not running");
+        }
+        for session in &self.sessions {
+            let since = UNIX_EPOCH + Duration::from_secs(session.since);
+            let since = humantime::format_rfc3339_seconds(since);
+            let _ = writeln!(
+                text,
+                "  {} {} (since {since})",
+                session.pid, session.command
+            );
+        }
+        text
+    }
+}
+
+/// Name of the value as it is given in the command line
+fn value_name<T: ValueEnum>(value: &T) -> String {
+    value
+        .to_possible_value()
+        .map(|v| v.get_name().to_string())
+        .unwrap_or_default()
+}
+
+/// Fields of the os-release file of the image, empty if it has none
+fn os_release(image: &Path) -> BTreeMap<String, String> {
+    let content = ["etc/os-release", "usr/lib/os-release"]
+        .into_iter()
+        .find_map(|path| std::fs::read_to_string(inside(image, &image.join(path))).ok())
+        .unwrap_or_default();
+    content
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| {
+            let value = value.trim().trim_matches(|c| c == '"' || c == '\'');
+            (key.trim().to_string(), value.to_string())
+        })
+        .collect()
+}
+
+/// Follows a symlink of the image, whose absolute targets are relative to the root of the image
+fn inside(image: &Path, path: &Path) -> PathBuf {
+    match symlink_metadata(path) {
+        Ok(meta) if meta.is_symlink() => match read_link(path) {
+            Ok(link) if link.is_absolute() => image.join(link.strip_prefix("/").unwrap_or(&link)),
+            Ok(link) => path.parent().unwrap_or(image).join(link),
+            Err(_) => path.to_path_buf(),
+        },
+        _ => path.to_path_buf(),
+    }
+}
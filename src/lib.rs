@@ -7,9 +7,27 @@
 
 // TODO: add documentation
 
+pub mod archive;
+pub mod clone;
+pub mod compression;
 pub mod config;
+pub mod copy;
 pub mod create;
+pub mod environment;
+pub mod export;
+pub mod init;
+pub mod inspect;
 pub mod list;
 pub mod namespaces;
+pub mod network;
+pub mod oci;
+pub mod ps;
+pub mod registry;
 pub mod remove;
 pub mod run;
+pub mod session;
+pub mod snapshot;
+pub mod stop;
+pub mod subid;
+pub mod usage;
+pub mod user;
@@ -2,70 +2,167 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use std::{borrow::Cow, env};
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
 
-use clap::Args;
+use clap::{Args, ValueEnum};
 use color_eyre::eyre;
 use color_eyre::eyre::WrapErr;
-use tabled::{Style, Table, Tabled};
+use serde::Serialize;
+use tabled::object::Columns;
+use tabled::{Disable, Style, Table, Tabled};
 
-use crate::config::{Config, STORAGE};
+use crate::config::{storage, Config};
+use crate::session::Session;
+use crate::usage::{Size, Usage};
 
 /// List toolboxes
 #[derive(Args, PartialEq, Eq, Debug)]
-pub struct List {}
+pub struct List {
+    #[clap(long, value_parser, default_value = "table")]
+    /// Format of the output, json and toml include the whole configuration
+    format: Format,
+    #[clap(short, long, value_parser)]
+    /// Only print the names of the toolboxes
+    quiet: bool,
+    #[clap(short, long, value_parser)]
+    /// Show the disk usage of each toolbox, which can take a while for big images
+    size: bool,
+    #[clap(long, value_parser, default_value = "name")]
+    /// Order of the toolboxes, the biggest or newest ones first
+    sort: Sort,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, ValueEnum)]
+enum Sort {
+    Name,
+    Size,
+    Created,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, ValueEnum)]
+enum Format {
+    Table,
+    Json,
+    Toml,
+}
 
+#[derive(Serialize)]
 struct Row {
     name: String,
+    /// Whether the root filesystem of the toolbox is still there
+    image_exists: bool,
+    /// Whether the toolbox has running sessions
+    running: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    usage: Option<Usage>,
+    #[serde(flatten)]
     config: Config,
 }
 
 impl Row {
-    fn new(name: String) -> eyre::Result<Self> {
+    fn new(name: String, size: bool) -> eyre::Result<Self> {
         let config = Config::read_or_new(&name)?;
-        Ok(Self { name, config })
+        let image = Path::new(&config.image);
+        Ok(Self {
+            image_exists: image.is_dir(),
+            running: !Session::live(&name)?.is_empty(),
+            usage: size.then(|| Usage::of(image)),
+            name,
+            config,
+        })
     }
+
+    fn created(&self) -> String {
+        match self.config.created {
+            Some(secs) => {
+                let created = UNIX_EPOCH + Duration::from_secs(secs);
+                humantime::format_rfc3339_seconds(created).to_string()
+            }
+            None => "-".into(),
+        }
+    }
+}
+
+/// Top level table of the TOML output, which cannot be an array
+#[derive(Serialize)]
+struct Inventory {
+    toolbox: Vec<Row>,
 }
 
 impl Tabled for Row {
-    const LENGTH: usize = 4;
+    const LENGTH: usize = 8;
 
     fn fields(&self) -> Vec<Cow<'_, str>> {
+        let usage = self.usage.unwrap_or_default();
         vec![
             Cow::Borrowed(&self.name),
             Cow::Borrowed(&self.config.shell),
             Cow::Borrowed(&self.config.hostname),
             Cow::Borrowed(&self.config.image),
+            Cow::Owned(self.created()),
+            Cow::Borrowed(self.config.source.as_deref().unwrap_or("-")),
+            Cow::Owned(Size(usage.apparent).to_string()),
+            Cow::Owned(Size(usage.real).to_string()),
         ]
     }
     fn headers() -> Vec<Cow<'static, str>> {
-        ["name", "shell", "hostname", "image"]
-            .into_iter()
-            .map(Cow::from)
-            .collect()
+        [
+            "name", "shell", "hostname", "image", "created", "source", "size", "disk",
+        ]
+        .into_iter()
+        .map(Cow::from)
+        .collect()
     }
 }
 
-pub fn list() -> eyre::Result<()> {
-    let home = env::var("HOME").wrap_err("Could not find current home")?;
-    let storage = format!("{home}/{STORAGE}/images");
-    let paths = match std::fs::read_dir(storage) {
-        Ok(paths) => paths,
-        Err(_) => {
-            help();
-            return Ok(());
-        }
-    };
-    let rows: Vec<Row> = paths
+pub fn list(args: List) -> eyre::Result<()> {
+    let storage = storage()?;
+    // Toolboxes living outside of the storage are only found through their meta file
+    let names: BTreeSet<String> = ["images", "meta"]
+        .into_iter()
+        .filter_map(|dir| std::fs::read_dir(storage.join(dir)).ok())
+        .flatten()
         .filter_map(|p| p.ok()?.file_name().into_string().ok())
-        .filter_map(|p| Row::new(p).ok())
+        // Hidden entries are copies that a rollback is about to restore
+        .filter(|p| !p.starts_with('.'))
+        .map(|p| p.strip_suffix(".toml").map(String::from).unwrap_or(p))
         .collect();
-    if rows.is_empty() {
-        help();
-    } else {
-        let mut table = Table::new(rows);
-        let table = table.with(Style::modern());
-        print!("{table}");
+    let size = args.size || args.sort == Sort::Size;
+    let mut rows: Vec<Row> = names
+        .into_iter()
+        .filter_map(|p| Row::new(p, size).ok())
+        .collect();
+    match args.sort {
+        Sort::Name => {}
+        Sort::Size => rows.sort_by_key(|row| std::cmp::Reverse(row.usage.map(|u| u.real))),
+        Sort::Created => rows.sort_by_key(|row| std::cmp::Reverse(row.config.created)),
+    }
+    if args.quiet {
+        rows.iter().for_each(|row| println!("{}", row.name));
+        return Ok(());
+    }
+    match args.format {
+        Format::Table if rows.is_empty() => help(),
+        Format::Table => {
+            let mut table = Table::new(rows);
+            let mut table = table.with(Style::modern());
+            if !size {
+                table = table.with(Disable::column(Columns::new(6..)));
+            }
+            print!("{table}");
+        }
+        Format::Json => {
+            let json = serde_json::to_string_pretty(&rows).wrap_err("Could not format as JSON")?;
+            println!("{json}");
+        }
+        Format::Toml => {
+            let inventory = Inventory { toolbox: rows };
+            let toml = toml::to_string(&inventory).wrap_err("Could not format as TOML")?;
+            print!("{toml}");
+        }
     }
     Ok(())
 }
@@ -73,6 +170,6 @@ pub fn list() -> eyre::Result<()> {
 fn help() {
     println!("No images could be found, maybe you want to create a new one first:");
     println!();
-    println!("\t unbox create <name> -i <container image url> -e <container engine>");
+    println!("\t unbox create <name> -i <container image url>");
     println!("\t unbox create <name> -t <tar file>");
 }
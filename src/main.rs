@@ -20,16 +20,27 @@ struct UnBox {
 #[derive(Subcommand, PartialEq, Eq, Debug)]
 enum Subcommands {
     Create(create::Create),
+    Clone(clone::CloneToolbox),
     #[clap(alias = "cfg")]
     Configure(config::Configure),
     Enter(run::Enter),
     Run(run::Run),
+    Exec(run::Exec),
     #[clap(alias = "rm")]
     Remove(remove::Remove),
     #[clap(alias = "ls")]
     List(list::List),
+    Inspect(inspect::Inspect),
+    Export(export::Export),
+    Import(export::Import),
+    Snapshot(snapshot::Snapshot),
+    Rollback(snapshot::Rollback),
+    Ps(ps::Ps),
+    Stop(stop::Stop),
     #[clap(hide = true)]
     SetMappings(namespaces::SetMappings),
+    #[clap(hide = true)]
+    Init(init::Init),
 }
 
 fn main() -> eyre::Result<()> {
@@ -37,16 +48,43 @@ fn main() -> eyre::Result<()> {
     color_eyre::config::HookBuilder::default()
         .display_env_section(false)
         .install()?;
-    config::setup()?;
     let cmd = UnBox::parse();
+    if let Err(error) = config::setup() {
+        return match cmd.subcommands {
+            Subcommands::Enter(_)
+            | Subcommands::Run(_)
+            | Subcommands::Exec(_)
+            | Subcommands::Init(_) => exit_on_error(Err(error)),
+            _ => Err(error),
+        };
+    }
 
     match cmd.subcommands {
         Subcommands::Create(args) => create::create(args),
-        Subcommands::Enter(args) => run::nsexec(run::Execute::Enter(args)),
-        Subcommands::Run(args) => run::nsexec(run::Execute::Run(args)),
+        Subcommands::Clone(args) => clone::clone(args),
+        Subcommands::Enter(args) => exit_on_error(run::nsexec(run::Execute::Enter(args))),
+        Subcommands::Run(args) => exit_on_error(run::nsexec(run::Execute::Run(args))),
+        Subcommands::Exec(args) => exit_on_error(run::nsexec(run::Execute::Exec(args))),
         Subcommands::Configure(args) => config::configure(args),
         Subcommands::Remove(args) => remove::remove(args),
-        Subcommands::List(_) => list::list(),
+        Subcommands::List(args) => list::list(args),
+        Subcommands::Inspect(args) => inspect::inspect(args),
+        Subcommands::Export(args) => export::export(args),
+        Subcommands::Import(args) => export::import(args),
+        Subcommands::Snapshot(args) => snapshot::snapshot(args),
+        Subcommands::Rollback(args) => snapshot::rollback(args),
+        Subcommands::Ps(_) => ps::ps(),
+        Subcommands::Stop(args) => stop::stop(args),
         Subcommands::SetMappings(_) => namespaces::set_mappings(),
+        Subcommands::Init(args) => exit_on_error(init::init(args)),
+    }
+}
+
+/// Reports the error and exits with the code that `run` and `enter` promise to scripts
+fn exit_on_error(result: eyre::Result<()>) -> eyre::Result<()> {
+    if let Err(error) = result {
+        eprintln!("Error: {error:?}");
+        std::process::exit(run::exit_code(&error));
     }
+    Ok(())
 }
@@ -6,17 +6,23 @@ use std::fmt::Display;
 use std::fs::{read_link, symlink_metadata};
 use std::io::Write;
 use std::os::unix::prelude::CommandExt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::{Child, Command, Stdio};
 
-use clap::Args;
+use clap::{Args, ValueEnum};
 use color_eyre::eyre;
 use color_eyre::eyre::WrapErr;
-use nix::sched::{unshare, CloneFlags};
+use nix::sched::{setns, unshare, CloneFlags};
 use nix::unistd::{pivot_root, sethostname};
+use serde::{Deserialize, Serialize};
 use std::ffi::{OsStr, OsString};
 
-use crate::config::MountInfo;
+use crate::config::{MountInfo, MountOptions};
+use crate::init::InitProcess;
+use crate::subid::{self, SubIds};
+
+/// Where the root of the host is found inside of the toolbox after pivoting
+pub const OLD_ROOT: &str = "/host";
 
 // Setup the uid and gid mappings inside the namespace
 /// Internal subcommand. Should not be used directly
@@ -75,7 +81,7 @@ where
 }
 
 pub struct Namespace<T> {
-    mapper: Child,
+    mapper: Option<Child>,
     typestate: std::marker::PhantomData<T>,
 }
 
@@ -87,16 +93,76 @@ pub struct Toolbox;
 
 impl<T> Namespace<T> {
     pub fn wait(&mut self) {
-        self.mapper.wait().expect("interrupted");
+        if let Some(mapper) = &mut self.mapper {
+            mapper.wait().expect("interrupted");
+        }
     }
 }
 
 impl Namespace<Setup> {
     pub fn start(
         flags: CloneFlags,
-        uid_mappings: &[Mapping<'_>],
-        gid_mappings: &[Mapping<'_>],
+        uid_mappings: &[Mapping],
+        gid_mappings: &[Mapping],
     ) -> eyre::Result<Namespace<Pivoter>> {
+        let mapper = match Mapper::detect() {
+            Mapper::Helpers => Some(Namespace::map_with_helpers(
+                flags,
+                uid_mappings,
+                gid_mappings,
+            )?),
+            Mapper::Direct => {
+                unshare(flags).wrap_err("Could not change namespace")?;
+                map_directly(uid_mappings, gid_mappings)?;
+                None
+            }
+        };
+        let next = Namespace {
+            mapper,
+            typestate: std::marker::PhantomData,
+        };
+        Ok(next)
+    }
+
+    /// Joins the namespaces of a running session instead of creating new ones
+    pub fn join(pid: u32) -> eyre::Result<Namespace<Toolbox>> {
+        use std::os::unix::fs::MetadataExt;
+        use std::os::unix::io::AsRawFd;
+        let namespaces = [
+            ("user", CloneFlags::CLONE_NEWUSER),
+            ("mnt", CloneFlags::CLONE_NEWNS),
+            ("uts", CloneFlags::CLONE_NEWUTS),
+            ("net", CloneFlags::CLONE_NEWNET),
+            ("pid_for_children", CloneFlags::CLONE_NEWPID),
+        ];
+        // Every namespace is opened first because joining the mount namespace changes /proc
+        let mut files = Vec::with_capacity(namespaces.len());
+        for (kind, flag) in namespaces {
+            let path = format!("/proc/{pid}/ns/{kind}");
+            let file =
+                std::fs::File::open(&path).wrap_err("Could not open the session namespaces")?;
+            let theirs = file.metadata()?;
+            let own = std::fs::metadata(format!("/proc/self/ns/{kind}"))?;
+            // The session may share some namespaces with the host, they cannot be joined again
+            if (theirs.dev(), theirs.ino()) != (own.dev(), own.ino()) {
+                files.push((file, flag));
+            }
+        }
+        for (file, flag) in files {
+            setns(file.as_raw_fd(), flag).wrap_err("Could not join the session namespaces")?;
+        }
+        let next = Namespace {
+            mapper: None,
+            typestate: std::marker::PhantomData,
+        };
+        Ok(next)
+    }
+
+    fn map_with_helpers(
+        flags: CloneFlags,
+        uid_mappings: &[Mapping],
+        gid_mappings: &[Mapping],
+    ) -> eyre::Result<Child> {
         let pid = std::process::id().to_string();
         let child = Command::new("/proc/self/exe")
             .arg("set-mappings")
@@ -111,31 +177,100 @@ impl Namespace<Setup> {
         let gid_argv = mappings_argv(&pid, gid_mappings);
         writeln!(child_in, "{}", uid_argv).expect("communication failed");
         writeln!(child_in, "{}", gid_argv).expect("communication failed");
+        Ok(child)
+    }
+}
 
-        let next = Namespace {
-            mapper: child,
-            typestate: std::marker::PhantomData,
-        };
-        Ok(next)
+/// Mechanism used to write the id mappings of a new user namespace
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Mapper {
+    /// The setuid `newuidmap` and `newgidmap` helpers, that can map subordinate ids
+    Helpers,
+    /// Writing to `/proc/self/uid_map` directly, only the ids of the current user can be mapped
+    Direct,
+}
+
+impl Mapper {
+    pub fn detect() -> Self {
+        if in_path("newuidmap") && in_path("newgidmap") {
+            Mapper::Helpers
+        } else {
+            Mapper::Direct
+        }
+    }
+
+    /// Subordinate uids and gids of the user that can be mapped, none without the helpers
+    pub fn ranges(&self) -> eyre::Result<(Vec<SubIds>, Vec<SubIds>)> {
+        match self {
+            Mapper::Helpers => Ok((subid::subuids()?, subid::subgids()?)),
+            Mapper::Direct => Ok((Vec::new(), Vec::new())),
+        }
     }
 }
 
-fn mappings_argv<'a>(pid: &'a str, mappings: &[Mapping<'a>]) -> String {
+/// Enters a user namespace with the same mappings as `run`, where the user is root and can read,
+/// write and remove the files that the subordinate ids of a toolbox created
+pub fn enter_user_namespace() -> eyre::Result<()> {
+    let uid = users::get_current_uid();
+    // Root can already access every file, but only the ids that are mapped inside of a namespace
+    if uid == 0 {
+        return Ok(());
+    }
+    let gid = users::get_current_gid();
+    let (subuids, subgids) = Mapper::detect().ranges()?;
+    let uid_mappings = UserNs::Root.mappings(uid, &subuids);
+    let gid_mappings = UserNs::Root.mappings(gid, &subgids);
+    let mut namespace = Namespace::start(CloneFlags::CLONE_NEWUSER, &uid_mappings, &gid_mappings)
+        .wrap_err("Could not enter a user namespace")?;
+    namespace.wait();
+    Ok(())
+}
+
+fn in_path(program: &str) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    std::env::split_paths(&path)
+        .filter_map(|dir| std::fs::metadata(dir.join(program)).ok())
+        .any(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+}
+
+/// Writes the mappings of the current process, the kernel only accepts the ids of the current user
+fn map_directly(uid_mappings: &[Mapping], gid_mappings: &[Mapping]) -> eyre::Result<()> {
+    let uid_map: String = uid_mappings.iter().map(Mapping::to_string).collect();
+    let gid_map: String = gid_mappings.iter().map(Mapping::to_string).collect();
+    std::fs::write("/proc/self/uid_map", uid_map).wrap_err("Could not write the uid_map")?;
+    // Unprivileged processes can only write the gid_map after denying setgroups
+    std::fs::write("/proc/self/setgroups", "deny").wrap_err("Could not deny setgroups")?;
+    std::fs::write("/proc/self/gid_map", gid_map).wrap_err("Could not write the gid_map")
+}
+
+fn mappings_argv(pid: &str, mappings: &[Mapping]) -> String {
+    use std::fmt::Write;
     let mut argv = String::with_capacity(10 * mappings.len());
     argv.push_str(pid);
     argv.push(' ');
     for map in mappings {
-        argv.push_str(map.inside);
-        argv.push(' ');
-        argv.push_str(map.outside);
-        argv.push(' ');
-        argv.push_str(map.len);
-        argv.push(' ');
+        write!(argv, "{} {} {} ", map.inside, map.outside, map.len).expect("infallible");
     }
     argv
 }
 
 impl Namespace<Pivoter> {
+    /// Spawns the init process of the PID namespace, see `InitProcess::spawn`
+    pub fn spawn_init<C, A>(
+        &mut self,
+        cmd: C,
+        args: &[A],
+        identity: Identity,
+    ) -> eyre::Result<InitProcess>
+    where
+        C: AsRef<OsStr>,
+        A: AsRef<OsStr>,
+    {
+        self.wait();
+        InitProcess::spawn(cmd, args, identity)
+    }
+
     pub fn pivot(self, new_root: &OsStr, old_root: &OsStr) -> eyre::Result<Namespace<Toolbox>> {
         // We have to bind mount the new root to itself because it is part of the old root
         bind_mount(new_root, new_root)?;
@@ -153,32 +288,163 @@ impl Namespace<Toolbox> {
     where
         I: Iterator<Item = MountInfo>,
     {
-        mounts
-            .map(|m| (follow_symlink(m.source), m.target))
-            .try_for_each(|m| bind_mount(&m.0, &m.1))
+        for mount in mounts {
+            let source = follow_symlink(mount.source);
+            create_target(&source, &mount.target)?;
+            bind_mount(&source, &mount.target)?;
+            apply_options(&mount.target, mount.options).wrap_err(format!(
+                "Could not apply the mount options to {:?}",
+                mount.target
+            ))?;
+        }
+        Ok(())
     }
 
     pub fn hostname(&self, name: &str) -> eyre::Result<()> {
         sethostname(name).wrap_err("Could not change the hostname")
     }
 
-    pub fn spawn<S>(&mut self, cmd: S, args: &[S]) -> eyre::Result<()>
+    /// Changes to the host directory as seen from inside of the toolbox, falling back to home
+    pub fn workdir(&self, host_dir: Option<&Path>, mounts: &[MountInfo], home: &str) -> PathBuf {
+        use std::env::{set_current_dir, set_var};
+        let dir = host_dir.map(|dir| translate_path(dir, mounts));
+        if let Some(dir) = dir.filter(|dir| set_current_dir(dir).is_ok()) {
+            set_var("PWD", &dir);
+            return dir;
+        }
+        let fallback = [home, "/"]
+            .into_iter()
+            .find(|dir| set_current_dir(dir).is_ok())
+            .unwrap_or("/");
+        eprintln!("Notice: the current directory is not available in the toolbox, using {fallback} instead");
+        set_var("PWD", fallback);
+        PathBuf::from(fallback)
+    }
+
+    pub fn spawn<S>(&mut self, cmd: S, args: &[S], identity: Identity) -> eyre::Result<()>
     where
         S: AsRef<OsStr>,
     {
         self.wait();
-        Command::new(cmd).args(args).exec();
-        eyre::bail!("Could not execute the requested command")
+        let error = Command::new(&cmd)
+            .args(args)
+            .uid(identity.uid)
+            .gid(identity.gid)
+            .exec();
+        Err(ExecError {
+            command: cmd.as_ref().to_os_string(),
+            source: error,
+        }
+        .into())
     }
 }
 
-pub struct Mapping<'a> {
-    pub inside: &'a str,
-    pub outside: &'a str,
-    pub len: &'a str,
+/// Failure to execute the command requested by the user inside of the toolbox
+#[derive(Debug)]
+pub struct ExecError {
+    pub command: OsString,
+    pub source: std::io::Error,
 }
 
-impl Display for Mapping<'_> {
+impl ExecError {
+    /// Whether the command exists but could not be executed
+    pub fn not_executable(&self) -> bool {
+        use nix::errno::Errno;
+        let errno = self.source.raw_os_error().map(Errno::from_i32);
+        !matches!(errno, Some(Errno::ENOENT) | Some(Errno::ENOTDIR))
+    }
+}
+
+impl Display for ExecError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Could not execute {:?}: {}", self.command, self.source)
+    }
+}
+
+impl std::error::Error for ExecError {}
+
+/// How the ids of the current user are mapped inside of the namespace
+#[derive(
+    Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Debug, ValueEnum, Serialize, Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum UserNs {
+    /// The user is root inside of the toolbox
+    #[default]
+    Root,
+    /// The user keeps its own uid and gid inside of the toolbox
+    KeepId,
+}
+
+impl UserNs {
+    /// Id that corresponds to the given id of the current user inside of the namespace
+    pub fn inside(&self, id: u32) -> u32 {
+        match self {
+            UserNs::Root => 0,
+            UserNs::KeepId => id,
+        }
+    }
+
+    /// Maps the id of the current user and fills the rest of the ids with the subordinate ranges
+    pub fn mappings(&self, id: u32, ranges: &[SubIds]) -> Vec<Mapping> {
+        let own = self.inside(id);
+        let mut mappings = vec![Mapping {
+            inside: own,
+            outside: id,
+            len: 1,
+        }];
+        let mut next: u32 = 0;
+        for range in ranges {
+            let (mut outside, mut left) = (range.start, range.count);
+            while left > 0 {
+                if next == own {
+                    next += 1;
+                    continue;
+                }
+                // Ranges below our own id are split so that it is not mapped twice
+                let len = match own.checked_sub(next) {
+                    Some(gap) if gap > 0 => left.min(gap),
+                    _ => left.min(u32::MAX - next),
+                };
+                if len == 0 {
+                    return mappings;
+                }
+                mappings.push(Mapping {
+                    inside: next,
+                    outside,
+                    len,
+                });
+                next += len;
+                outside += len;
+                left -= len;
+            }
+        }
+        mappings
+    }
+
+    pub fn identity(&self) -> Identity {
+        Identity {
+            uid: self.inside(users::get_current_uid()),
+            gid: self.inside(users::get_current_gid()),
+        }
+    }
+}
+
+/// User and group used to execute commands inside of the namespace
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Identity {
+    pub uid: u32,
+    pub gid: u32,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Mapping {
+    pub inside: u32,
+    pub outside: u32,
+    pub len: u32,
+}
+
+impl Display for Mapping {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "{} {} {}", self.inside, self.outside, self.len)
     }
@@ -198,7 +464,120 @@ fn bind_mount(source: &OsStr, target: &OsStr) -> eyre::Result<()> {
     ))
 }
 
-fn follow_symlink(path: OsString) -> OsString {
+/// Mounts added from the command line can point to paths that do not exist in the image
+fn create_target(source: &OsStr, target: &OsStr) -> eyre::Result<()> {
+    let target = Path::new(target);
+    if symlink_metadata(target).is_ok() {
+        return Ok(());
+    }
+    let created = match Path::new(source).is_dir() {
+        true => std::fs::create_dir_all(target),
+        false => {
+            let parent = target.parent().unwrap_or(Path::new("/"));
+            std::fs::create_dir_all(parent).and_then(|_| std::fs::File::create(target).map(drop))
+        }
+    };
+    created.wrap_err(format!("Could not create the mount point {target:?}"))
+}
+
+fn apply_options(target: &OsStr, options: MountOptions) -> eyre::Result<()> {
+    use nix::mount::MsFlags;
+    let mut flags = MsFlags::empty();
+    flags.set(MsFlags::MS_RDONLY, options.read_only);
+    flags.set(MsFlags::MS_NOSUID, options.nosuid);
+    flags.set(MsFlags::MS_NOEXEC, options.noexec);
+    if options.private {
+        nix::mount::mount::<str, OsStr, str, str>(
+            None,
+            target,
+            None,
+            MsFlags::MS_PRIVATE | MsFlags::MS_REC,
+            None,
+        )?;
+    }
+    if flags.is_empty() {
+        return Ok(());
+    }
+    // Remounting only changes one mount, the ones below it were bound recursively
+    for mount in std::iter::once(PathBuf::from(target)).chain(submounts(Path::new(target))?) {
+        let flags = MsFlags::MS_REMOUNT | MsFlags::MS_BIND | flags | locked_flags(&mount)?;
+        nix::mount::mount::<str, Path, str, str>(None, &mount, None, flags, None)?;
+    }
+    Ok(())
+}
+
+/// Flags of a mount that cannot be cleared from inside of a user namespace, so remounts keep them
+fn locked_flags(mount: &Path) -> eyre::Result<nix::mount::MsFlags> {
+    use nix::mount::MsFlags;
+    use nix::sys::statvfs::{statvfs, FsFlags};
+    let current = statvfs(mount)?.flags();
+    let pairs = [
+        (FsFlags::ST_RDONLY, MsFlags::MS_RDONLY),
+        (FsFlags::ST_NOSUID, MsFlags::MS_NOSUID),
+        (FsFlags::ST_NODEV, MsFlags::MS_NODEV),
+        (FsFlags::ST_NOEXEC, MsFlags::MS_NOEXEC),
+        (FsFlags::ST_NOATIME, MsFlags::MS_NOATIME),
+        (FsFlags::ST_NODIRATIME, MsFlags::MS_NODIRATIME),
+        (FsFlags::ST_RELATIME, MsFlags::MS_RELATIME),
+    ];
+    Ok(pairs
+        .into_iter()
+        .filter(|(fs, _)| current.contains(*fs))
+        .fold(MsFlags::empty(), |flags, (_, ms)| flags | ms))
+}
+
+/// Mount points below `target`, as listed in the mountinfo of the process
+fn submounts(target: &Path) -> eyre::Result<Vec<PathBuf>> {
+    // The proc of the toolbox may not be mounted yet, the one of the host is always there
+    let info = std::fs::read_to_string(format!("{OLD_ROOT}/proc/self/mountinfo"))?;
+    Ok(info
+        .lines()
+        .filter_map(|line| line.split(' ').nth(4))
+        .map(|point| PathBuf::from(unescape(point)))
+        .filter(|point| point != target && point.starts_with(target))
+        .collect())
+}
+
+/// Undoes the octal escapes of spaces, tabs, newlines and backslashes in mountinfo
+fn unescape(field: &str) -> OsString {
+    use std::os::unix::ffi::OsStringExt;
+    let bytes = field.as_bytes();
+    let mut unescaped = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = bytes.get(i + 1..i + 4).filter(|_| bytes[i] == b'\\');
+        match escape.and_then(|octal| u8::from_str_radix(std::str::from_utf8(octal).ok()?, 8).ok())
+        {
+            Some(byte) => {
+                unescaped.push(byte);
+                i += 4;
+            }
+            None => {
+                unescaped.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    OsString::from_vec(unescaped)
+}
+
+/// Finds a path of the host inside of the toolbox, through the deepest mount that contains it
+fn translate_path(path: &Path, mounts: &[MountInfo]) -> PathBuf {
+    let host = Path::new(OLD_ROOT);
+    mounts
+        .iter()
+        .filter_map(|m| {
+            let source = PathBuf::from(follow_symlink(m.source.clone()));
+            let source = source.strip_prefix(host).ok()?;
+            let rest = path.strip_prefix(Path::new("/").join(source)).ok()?;
+            Some((source.components().count(), Path::new(&m.target).join(rest)))
+        })
+        .max_by_key(|(depth, _)| *depth)
+        .map(|(_, translated)| translated)
+        .unwrap_or_else(|| host.join(path.strip_prefix("/").unwrap_or(path)))
+}
+
+pub fn follow_symlink(path: OsString) -> OsString {
     match symlink_metadata(&path) {
         Ok(meta) if meta.is_symlink() => {
             let path = PathBuf::from(path);
@@ -0,0 +1,85 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use clap::ValueEnum;
+use color_eyre::eyre;
+use color_eyre::eyre::WrapErr;
+use nix::errno::Errno;
+use nix::net::if_::if_nametoindex;
+use nix::sys::socket::{
+    recv, sendto, socket, AddressFamily, MsgFlags, NetlinkAddr, SockFlag, SockProtocol, SockType,
+};
+use nix::unistd::close;
+use serde::{Deserialize, Serialize};
+
+const RTM_NEWLINK: u16 = 16;
+const NLMSG_ERROR: u16 = 2;
+const NLM_F_REQUEST: u16 = 1;
+const NLM_F_ACK: u16 = 4;
+const IFF_UP: u32 = 1;
+
+/// Network that the processes of the toolbox have access to
+#[derive(
+    Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Debug, ValueEnum, Serialize, Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Network {
+    /// The network of the host is shared with the toolbox
+    #[default]
+    Host,
+    /// No network at all, not even a loopback interface
+    None,
+    /// Only a loopback interface that is not shared with the host
+    Loopback,
+}
+
+impl Network {
+    /// Whether the toolbox needs its own network namespace
+    pub fn isolated(&self) -> bool {
+        !matches!(self, Network::Host)
+    }
+}
+
+/// Brings up the loopback interface of the current network namespace
+pub fn loopback_up() -> eyre::Result<()> {
+    let index = if_nametoindex("lo").wrap_err("Could not find the loopback interface")?;
+    let socket = socket(
+        AddressFamily::Netlink,
+        SockType::Raw,
+        SockFlag::SOCK_CLOEXEC,
+        SockProtocol::NetlinkRoute,
+    )
+    .wrap_err("Could not open a netlink socket")?;
+    let result = set_link_up(socket, index);
+    let _ = close(socket);
+    result.wrap_err("Could not bring up the loopback interface")
+}
+
+fn set_link_up(socket: i32, index: u32) -> eyre::Result<()> {
+    // struct nlmsghdr followed by struct ifinfomsg, both in native endianness
+    let mut request = Vec::with_capacity(32);
+    request.extend_from_slice(&32u32.to_ne_bytes());
+    request.extend_from_slice(&RTM_NEWLINK.to_ne_bytes());
+    request.extend_from_slice(&(NLM_F_REQUEST | NLM_F_ACK).to_ne_bytes());
+    request.extend_from_slice(&1u32.to_ne_bytes());
+    request.extend_from_slice(&0u32.to_ne_bytes());
+    request.extend_from_slice(&[0, 0]);
+    request.extend_from_slice(&0u16.to_ne_bytes());
+    request.extend_from_slice(&(index as i32).to_ne_bytes());
+    request.extend_from_slice(&IFF_UP.to_ne_bytes());
+    request.extend_from_slice(&IFF_UP.to_ne_bytes());
+    sendto(socket, &request, &NetlinkAddr::new(0, 0), MsgFlags::empty())?;
+
+    let mut response = [0; 1024];
+    let len = recv(socket, &mut response, MsgFlags::empty())?;
+    let kind = response.get(4..6).map(|b| u16::from_ne_bytes([b[0], b[1]]));
+    let error = response[..len]
+        .get(16..20)
+        .map(|b| i32::from_ne_bytes([b[0], b[1], b[2], b[3]]));
+    match (kind, error) {
+        (Some(NLMSG_ERROR), Some(0)) => Ok(()),
+        (Some(NLMSG_ERROR), Some(error)) => Err(Errno::from_i32(-error).into()),
+        _ => eyre::bail!("Unexpected netlink response"),
+    }
+}
@@ -0,0 +1,377 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::collections::HashSet;
+use std::fs::{remove_dir_all, remove_file, symlink_metadata, File};
+use std::io::{BufReader, Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+use color_eyre::eyre;
+use color_eyre::eyre::WrapErr;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tar::{Archive, EntryType};
+
+use crate::compression::decompress;
+
+const WHITEOUT_PREFIX: &str = ".wh.";
+const WHITEOUT_OPAQUE: &str = ".wh..wh..opq";
+const DOCKER_LAYER: &str = "application/vnd.docker.image.rootfs.diff.tar";
+
+/// Reference to a blob inside of a registry or an image layout
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Descriptor {
+    pub media_type: String,
+    pub digest: String,
+    pub size: u64,
+    pub platform: Option<Platform>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Platform {
+    pub architecture: String,
+    pub os: String,
+    pub variant: Option<String>,
+}
+
+impl Platform {
+    fn is_current(&self) -> bool {
+        self.os == "linux" && self.architecture == current_architecture()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Index {
+    pub manifests: Vec<Descriptor>,
+}
+
+impl Index {
+    /// Selects the manifest that can be executed on this machine
+    pub fn select(&self) -> eyre::Result<&Descriptor> {
+        // Manifests without a platform are common in single image layouts
+        let mut candidates = self
+            .manifests
+            .iter()
+            .filter(|m| m.platform.as_ref().is_none_or(Platform::is_current));
+        let first = candidates.next().ok_or_else(|| {
+            eyre::eyre!(
+                "The image is not available for linux/{}",
+                current_architecture()
+            )
+        })?;
+        let exact = std::iter::once(first)
+            .chain(candidates)
+            .find(|m| m.platform.as_ref().and_then(|p| p.variant.as_deref()) == current_variant());
+        Ok(exact.unwrap_or(first))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Manifest {
+    pub layers: Vec<Descriptor>,
+}
+
+/// Either a single image manifest or an index of manifests for multiple platforms
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ImageManifest {
+    Index(Index),
+    Manifest(Manifest),
+}
+
+/// Layer blob stored on disk, possibly compressed
+pub struct Layer {
+    pub path: PathBuf,
+    pub media_type: String,
+}
+
+/// Ordered set of layers that compose a root filesystem
+pub struct Image {
+    pub layers: Vec<Layer>,
+    /// Digest of the manifest or index that the image was selected from, if it has one
+    pub digest: Option<String>,
+}
+
+impl Image {
+    /// Applies every layer in order into the root directory
+    pub fn unpack(&self, root: &Path) -> eyre::Result<()> {
+        for layer in &self.layers {
+            eyre::ensure!(
+                layer.media_type.contains(".tar"),
+                "Unsupported layer media type {}",
+                layer.media_type
+            );
+            let file = File::open(&layer.path).wrap_err("Could not open the layer blob")?;
+            let reader = decompress(BufReader::new(file))?;
+            apply_layer(reader, root)
+                .wrap_err(format!("Could not apply the layer {:?}", layer.path))?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads the image stored in an OCI image layout directory
+pub fn from_layout(dir: &Path) -> eyre::Result<Image> {
+    let index: Index = serde_json::from_slice(&read(&dir.join("index.json"))?)
+        .wrap_err("Invalid image layout index")?;
+    let selected = index.select()?;
+    let manifest = layout_manifest(dir, selected)?;
+    let mut layers = Vec::with_capacity(manifest.layers.len());
+    for descriptor in manifest.layers {
+        let path = blob_path(dir, &descriptor.digest)?;
+        let mut verifier = Verifier::new(std::io::sink());
+        std::io::copy(&mut File::open(&path)?, &mut verifier)
+            .wrap_err("Could not read a layer of the image layout")?;
+        verifier.verify(&descriptor.digest)?;
+        layers.push(Layer {
+            path,
+            media_type: descriptor.media_type,
+        });
+    }
+    Ok(Image {
+        layers,
+        digest: Some(selected.digest.clone()),
+    })
+}
+
+fn layout_manifest(dir: &Path, descriptor: &Descriptor) -> eyre::Result<Manifest> {
+    let blob = read(&blob_path(dir, &descriptor.digest)?)?;
+    let mut verifier = Verifier::new(std::io::sink());
+    verifier.write_all(&blob)?;
+    verifier.verify(&descriptor.digest)?;
+    match serde_json::from_slice(&blob).wrap_err("Unsupported image manifest")? {
+        ImageManifest::Manifest(manifest) => Ok(manifest),
+        ImageManifest::Index(index) => layout_manifest(dir, index.select()?),
+    }
+}
+
+fn blob_path(dir: &Path, digest: &str) -> eyre::Result<PathBuf> {
+    let (algorithm, hex) = digest
+        .split_once(':')
+        .ok_or_else(|| eyre::eyre!("Invalid digest {digest}"))?;
+    let valid = [algorithm, hex]
+        .iter()
+        .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric()));
+    eyre::ensure!(valid, "Invalid digest {digest}");
+    Ok(dir.join("blobs").join(algorithm).join(hex))
+}
+
+/// Reads the image saved by `docker save`, extracting the archive into a directory. The directory
+/// has to exist and be private, because the layers are read again from it to unpack them
+pub fn from_docker_archive(archive: &Path, dir: &Path) -> eyre::Result<Image> {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Entry {
+        layers: Vec<String>,
+    }
+
+    let file = File::open(archive).wrap_err("Could not open the docker archive")?;
+    Archive::new(decompress(BufReader::new(file))?)
+        .unpack(dir)
+        .wrap_err("Could not extract the docker archive")?;
+    let entries: Vec<Entry> = serde_json::from_slice(&read(&dir.join("manifest.json"))?)
+        .wrap_err("Invalid docker archive manifest")?;
+    let entry = entries
+        .into_iter()
+        .next()
+        .ok_or_else(|| eyre::eyre!("The docker archive does not contain any image"))?;
+    let layers = entry
+        .layers
+        .iter()
+        .map(|layer| {
+            Ok(Layer {
+                path: dir.join(normalize(Path::new(layer))?),
+                media_type: DOCKER_LAYER.into(),
+            })
+        })
+        .collect::<eyre::Result<_>>()?;
+    Ok(Image {
+        layers,
+        digest: None,
+    })
+}
+
+fn read(path: &Path) -> eyre::Result<Vec<u8>> {
+    std::fs::read(path).wrap_err(format!("Could not read {path:?}"))
+}
+
+/// Unpacks a layer tarball into the root directory, honoring whiteout files
+pub fn apply_layer<R: Read>(layer: R, root: &Path) -> eyre::Result<()> {
+    let mut archive = Archive::new(layer);
+    let mut added = HashSet::new();
+    let mut dirs = Vec::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = normalize(&entry.path()?)?;
+        let name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or_default();
+        let parent = path.parent().unwrap_or_else(|| Path::new(""));
+        if name == WHITEOUT_OPAQUE {
+            clear_dir(root, parent, &added)?;
+        } else if let Some(hidden) = name.strip_prefix(WHITEOUT_PREFIX) {
+            remove_path(&whiteout_target(root, parent, hidden)?)?;
+        } else if entry.header().entry_type() == EntryType::Directory {
+            replace_conflicting(root, &path, true)?;
+            added.insert(path);
+            dirs.push(entry);
+        } else {
+            replace_conflicting(root, &path, false)?;
+            entry.unpack_in(root).wrap_err("Could not unpack entry")?;
+            added.insert(path);
+        }
+    }
+    dirs.sort_unstable_by_key(|b| std::cmp::Reverse(b.path_bytes().len()));
+    for mut dir in dirs {
+        dir.unpack_in(root)
+            .wrap_err("Could not unpack a directory")?;
+    }
+    Ok(())
+}
+
+/// Removes the contents of a directory that come from lower layers
+fn clear_dir(root: &Path, dir: &Path, added: &HashSet<PathBuf>) -> eyre::Result<()> {
+    let real = inside(root, dir)?;
+    let children = match std::fs::read_dir(&real) {
+        Ok(children) => children,
+        Err(_) => return Ok(()),
+    };
+    for child in children {
+        let child = child?.file_name();
+        let relative = dir.join(&child);
+        if !added.iter().any(|p| p.starts_with(&relative)) {
+            remove_path(&real.join(child))?;
+        }
+    }
+    Ok(())
+}
+
+/// Resolves a directory of the layer inside of the root, refusing to escape it through symlinks
+fn inside(root: &Path, dir: &Path) -> eyre::Result<PathBuf> {
+    let root = root.canonicalize()?;
+    match root.join(dir).canonicalize() {
+        Ok(real) if real.starts_with(&root) => Ok(real),
+        Ok(_) => Err(eyre::eyre!(
+            "Layer entry {dir:?} points outside of the root"
+        )),
+        // Whiteouts for directories that do not exist have nothing to hide
+        Err(_) => Ok(root.join(dir)),
+    }
+}
+
+/// Path hidden by a whiteout, which has to be an entry of its directory inside of the root
+fn whiteout_target(root: &Path, dir: &Path, hidden: &str) -> eyre::Result<PathBuf> {
+    let valid = !matches!(hidden, "" | "." | "..") && !hidden.contains('/');
+    eyre::ensure!(valid, "Invalid whiteout {hidden:?} in layer");
+    let target = inside(root, dir)?.join(hidden);
+    let root = root.canonicalize()?;
+    eyre::ensure!(
+        target.starts_with(&root) && target != root,
+        "Layer whiteout {target:?} points outside of the root"
+    );
+    Ok(target)
+}
+
+/// Makes an entry path relative to the root, rejecting any path that tries to escape it
+fn normalize(path: &Path) -> eyre::Result<PathBuf> {
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => normal.push(part),
+            Component::ParentDir => eyre::bail!("Invalid path in layer {path:?}"),
+            _ => (),
+        }
+    }
+    Ok(normal)
+}
+
+fn remove_path(path: &Path) -> eyre::Result<()> {
+    match symlink_metadata(path) {
+        Ok(meta) if meta.is_dir() => remove_dir_all(path),
+        Ok(_) => remove_file(path),
+        Err(_) => return Ok(()),
+    }
+    .wrap_err(format!("Could not remove {path:?}"))
+}
+
+/// Removes an entry from a lower layer when it has a different kind than the new one
+fn replace_conflicting(root: &Path, path: &Path, is_dir: bool) -> eyre::Result<()> {
+    match symlink_metadata(root.join(path)) {
+        Ok(meta) if meta.is_dir() != is_dir => {
+            let parent = path.parent().unwrap_or_else(|| Path::new(""));
+            let name = path.file_name().expect("entries have a name");
+            remove_path(&inside(root, parent)?.join(name))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Name of the architecture of this machine as used by OCI
+pub fn current_architecture() -> &'static str {
+    match std::env::consts::ARCH {
+        "x86_64" => "amd64",
+        "x86" => "386",
+        "aarch64" => "arm64",
+        "powerpc64" => "ppc64le",
+        "loongarch64" => "loong64",
+        arch => arch,
+    }
+}
+
+fn current_variant() -> Option<&'static str> {
+    match std::env::consts::ARCH {
+        "aarch64" => Some("v8"),
+        "arm" => Some("v7"),
+        _ => None,
+    }
+}
+
+/// Writer that computes the sha256 digest of everything written through it
+pub(crate) struct Verifier<W> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> Verifier<W> {
+    pub(crate) fn new(inner: W) -> Self {
+        Verifier {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    pub(crate) fn verify(self, digest: &str) -> eyre::Result<()> {
+        let expected = digest
+            .strip_prefix("sha256:")
+            .ok_or_else(|| eyre::eyre!("Unsupported digest algorithm in {digest}"))?;
+        let actual = self.digest();
+        let actual = actual.strip_prefix("sha256:").unwrap_or(&actual);
+        eyre::ensure!(
+            actual == expected,
+            "Digest mismatch, expected {expected} but got {actual}"
+        );
+        Ok(())
+    }
+}
+
+impl<W> Verifier<W> {
+    /// Digest of everything written so far, as `sha256:<hex>`
+    pub(crate) fn digest(self) -> String {
+        format!("sha256:{:x}", self.hasher.finalize())
+    }
+}
+
+impl<W: Write> Write for Verifier<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,76 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::borrow::Cow;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use clap::Args;
+use color_eyre::eyre;
+use tabled::{Style, Table, Tabled};
+
+use crate::session::Session;
+
+/// List running toolbox sessions
+#[derive(Args, PartialEq, Eq, Debug)]
+pub struct Ps {}
+
+struct Row {
+    session: Session,
+    pid: String,
+    running: String,
+}
+
+impl Row {
+    fn new(session: Session, now: u64) -> Self {
+        // Sub-minute precision is noise for sessions that usually last for hours
+        let running = Duration::from_secs(now.saturating_sub(session.since) / 60 * 60);
+        let running = match running.is_zero() {
+            true => "less than a minute".to_string(),
+            false => humantime::format_duration(running).to_string(),
+        };
+        Self {
+            pid: session.pid.to_string(),
+            session,
+            running,
+        }
+    }
+}
+
+impl Tabled for Row {
+    const LENGTH: usize = 4;
+
+    fn fields(&self) -> Vec<Cow<'_, str>> {
+        vec![
+            Cow::Borrowed(&self.session.name),
+            Cow::Borrowed(&self.pid),
+            Cow::Borrowed(&self.session.command),
+            Cow::Borrowed(&self.running),
+        ]
+    }
+    fn headers() -> Vec<Cow<'static, str>> {
+        ["name", "pid", "command", "running for"]
+            .into_iter()
+            .map(Cow::from)
+            .collect()
+    }
+}
+
+pub fn ps() -> eyre::Result<()> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let rows: Vec<Row> = Session::all()?
+        .into_iter()
+        .map(|s| Row::new(s, now))
+        .collect();
+    if rows.is_empty() {
+        println!("No toolbox is running");
+    } else {
+        let mut table = Table::new(rows);
+        let table = table.with(Style::modern());
+        print!("{table}");
+    }
+    Ok(())
+}
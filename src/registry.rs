@@ -0,0 +1,252 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::fmt::Display;
+use std::fs::{create_dir_all, File};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use color_eyre::eyre;
+use color_eyre::eyre::WrapErr;
+use serde::Deserialize;
+
+use crate::oci::{Image, ImageManifest, Layer, Manifest, Verifier};
+
+const DOCKER_HUB: &str = "docker.io";
+const DOCKER_HUB_ENDPOINT: &str = "registry-1.docker.io";
+
+const MANIFEST_TYPES: &str = "application/vnd.oci.image.index.v1+json, \
+     application/vnd.docker.distribution.manifest.list.v2+json, \
+     application/vnd.oci.image.manifest.v1+json, \
+     application/vnd.docker.distribution.manifest.v2+json";
+
+/// Location of an image inside of a registry, e.g. `docker.io/archlinux:latest`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    pub registry: String,
+    pub repository: String,
+    /// Either a tag or a digest
+    pub reference: String,
+}
+
+impl FromStr for Reference {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, digest) = match s.split_once('@') {
+            Some((name, digest)) => (name, Some(digest)),
+            None => (s, None),
+        };
+        let (name, tag) = match name.rsplit_once(':') {
+            Some((name, tag)) if !tag.contains('/') => (name, Some(tag)),
+            _ => (name, None),
+        };
+        let (registry, repository) = match name.split_once('/') {
+            Some((host, rest)) if is_registry(host) => (host, rest.to_string()),
+            _ => (DOCKER_HUB, name.to_string()),
+        };
+        let repository = if registry == DOCKER_HUB && !repository.contains('/') {
+            format!("library/{repository}")
+        } else {
+            repository
+        };
+        eyre::ensure!(!repository.is_empty(), "Invalid image reference {s}");
+        let reference = digest.or(tag).unwrap_or("latest");
+        Ok(Reference {
+            registry: registry.to_string(),
+            repository,
+            reference: reference.to_string(),
+        })
+    }
+}
+
+impl Display for Reference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let separator = if self.reference.contains(':') {
+            '@'
+        } else {
+            ':'
+        };
+        write!(
+            f,
+            "{}/{}{separator}{}",
+            self.registry, self.repository, self.reference
+        )
+    }
+}
+
+impl Reference {
+    fn endpoint(&self) -> String {
+        let host = match self.registry.as_str() {
+            DOCKER_HUB => DOCKER_HUB_ENDPOINT,
+            host => host,
+        };
+        if is_local(host) {
+            format!("http://{host}")
+        } else {
+            format!("https://{host}")
+        }
+    }
+}
+
+fn is_registry(host: &str) -> bool {
+    host.contains('.') || host.contains(':') || host == "localhost"
+}
+
+fn is_local(host: &str) -> bool {
+    let host = host.rsplit_once(':').map_or(host, |(host, _)| host);
+    host == "localhost" || host.starts_with("127.") || host == "[::1]"
+}
+
+/// Downloads every layer of the image into a directory, verifying their digests
+pub fn pull(reference: &Reference, dir: &Path) -> eyre::Result<Image> {
+    let mut client = Client::new(reference);
+    let (manifest, digest) = client.manifest(&reference.reference)?;
+    let manifest = match manifest {
+        ImageManifest::Manifest(manifest) => manifest,
+        ImageManifest::Index(index) => {
+            let selected = index.select()?;
+            match client.manifest(&selected.digest)?.0 {
+                ImageManifest::Manifest(manifest) => manifest,
+                ImageManifest::Index(_) => eyre::bail!("Nested image indexes are not supported"),
+            }
+        }
+    };
+    create_dir_all(dir).wrap_err("Could not create the layers directory")?;
+    download_layers(&mut client, manifest, digest, dir)
+}
+
+fn download_layers(
+    client: &mut Client,
+    manifest: Manifest,
+    digest: String,
+    dir: &Path,
+) -> eyre::Result<Image> {
+    let mut layers = Vec::with_capacity(manifest.layers.len());
+    for descriptor in manifest.layers {
+        let path = dir.join(descriptor.digest.replace(':', "-"));
+        let file = File::create(&path).wrap_err("Could not create the layer file")?;
+        let response = client.get(&format!("blobs/{}", descriptor.digest), "*/*")?;
+        let mut writer = Verifier::new(file);
+        std::io::copy(&mut response.into_reader(), &mut writer)
+            .wrap_err("Could not download a layer")?;
+        writer.verify(&descriptor.digest)?;
+        layers.push(Layer {
+            path,
+            media_type: descriptor.media_type,
+        });
+    }
+    Ok(Image {
+        layers,
+        digest: Some(digest),
+    })
+}
+
+struct Client {
+    agent: ureq::Agent,
+    base: String,
+    token: Option<String>,
+}
+
+impl Client {
+    fn new(reference: &Reference) -> Self {
+        Client {
+            agent: ureq::agent(),
+            base: format!("{}/v2/{}", reference.endpoint(), reference.repository),
+            token: None,
+        }
+    }
+
+    /// Fetches a manifest by tag or digest, together with its digest
+    fn manifest(&mut self, reference: &str) -> eyre::Result<(ImageManifest, String)> {
+        let response = self.get(&format!("manifests/{reference}"), MANIFEST_TYPES)?;
+        let mut body = Vec::new();
+        response
+            .into_reader()
+            .read_to_end(&mut body)
+            .wrap_err("Could not download the image manifest")?;
+        let mut verifier = Verifier::new(std::io::sink());
+        verifier.write_all(&body)?;
+        let digest = verifier.digest();
+        eyre::ensure!(
+            !reference.contains(':') || reference == digest,
+            "Digest mismatch, expected {reference} but got {digest}"
+        );
+        let manifest = serde_json::from_slice(&body).wrap_err("Unsupported image manifest")?;
+        Ok((manifest, digest))
+    }
+
+    fn get(&mut self, path: &str, accept: &str) -> eyre::Result<ureq::Response> {
+        let url = format!("{}/{path}", self.base);
+        let result = match self.request(&url, accept).call() {
+            Err(ureq::Error::Status(401, response)) if self.token.is_none() => {
+                self.authenticate(&response)?;
+                self.request(&url, accept).call()
+            }
+            result => result,
+        };
+        result.wrap_err(format!("Could not fetch {url}"))
+    }
+
+    fn request(&self, url: &str, accept: &str) -> ureq::Request {
+        let request = self.agent.get(url).set("Accept", accept);
+        match &self.token {
+            Some(token) => request.set("Authorization", &format!("Bearer {token}")),
+            None => request,
+        }
+    }
+
+    /// Gets an anonymous token following the challenge given by the registry
+    fn authenticate(&mut self, challenge: &ureq::Response) -> eyre::Result<()> {
+        #[derive(Deserialize)]
+        struct Token {
+            token: Option<String>,
+            access_token: Option<String>,
+        }
+
+        let header = challenge
+            .header("WWW-Authenticate")
+            .ok_or_else(|| eyre::eyre!("The registry requires an unsupported authentication"))?;
+        let params = header
+            .strip_prefix("Bearer ")
+            .ok_or_else(|| eyre::eyre!("The registry requires an unsupported authentication"))?;
+        let params = challenge_params(params);
+        let realm = params
+            .iter()
+            .find_map(|(k, v)| (*k == "realm").then_some(*v))
+            .ok_or_else(|| eyre::eyre!("The registry did not provide an authentication realm"))?;
+        let mut request = self.agent.get(realm);
+        for (key, value) in params.iter().filter(|(k, _)| *k != "realm") {
+            request = request.query(key, value);
+        }
+        let response = request
+            .call()
+            .wrap_err("Could not authenticate with the registry")?;
+        let token: Token = serde_json::from_reader(response.into_reader())
+            .wrap_err("Invalid authentication token")?;
+        self.token = token.token.or(token.access_token);
+        eyre::ensure!(
+            self.token.is_some(),
+            "The registry did not provide an authentication token"
+        );
+        Ok(())
+    }
+}
+
+/// Parses the `key="value"` pairs of a `WWW-Authenticate` challenge
+fn challenge_params(params: &str) -> Vec<(&str, &str)> {
+    let mut parsed = Vec::new();
+    let mut rest = params.trim();
+    while let Some((key, value)) = rest.split_once('=') {
+        let key = key.trim_start_matches(',').trim();
+        let (value, next) = match value.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => value.split_once(',').unwrap_or((value, "")),
+        };
+        parsed.push((key, value));
+        rest = next;
+    }
+    parsed
+}
@@ -2,16 +2,19 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use std::env;
-use std::fs::Permissions;
-use std::os::unix::prelude::PermissionsExt;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
 
 use clap::Args;
 use color_eyre::eyre;
 use color_eyre::eyre::WrapErr;
-use walkdir::WalkDir;
 
-use crate::config::{Config, STORAGE};
+use crate::config::{meta_path, Config};
+use crate::copy::remove_tree;
+use crate::namespaces::enter_user_namespace;
+use crate::session::Session;
+use crate::snapshot::{remove_snapshots, snapshot_tags};
+use crate::usage::{Size, Usage};
 
 /// Remove a toolbox
 #[derive(Args, PartialEq, Eq, Debug)]
@@ -19,31 +22,126 @@ pub struct Remove {
     #[clap(value_parser)]
     /// Names of the toolboxes to be removed
     pub names: Vec<String>,
+    #[clap(short, long, value_parser)]
+    /// Remove toolboxes even if they are running, and keep going when one cannot be removed
+    pub force: bool,
+    #[clap(short, long, value_parser)]
+    /// Do not ask for confirmation
+    pub yes: bool,
+    #[clap(long, value_parser)]
+    /// Only show what would be removed
+    pub dry_run: bool,
+}
+
+/// Toolbox that is going to be removed
+struct Target {
+    name: String,
+    config: Config,
+    meta: PathBuf,
+    /// Tags of the snapshots, which are removed with the toolbox
+    snapshots: Vec<String>,
 }
 
 pub fn remove(args: Remove) -> eyre::Result<()> {
-    for name in args.names {
-        remove_one(name)?;
+    if args.names.is_empty() {
+        return Ok(());
+    }
+    // Scripts would otherwise get an empty answer and remove nothing without noticing
+    eyre::ensure!(
+        args.yes || args.dry_run || std::io::stdin().is_terminal(),
+        "Removing toolboxes has to be confirmed, use -y when stdin is not a terminal"
+    );
+    enter_user_namespace()?;
+    let mut targets = Vec::with_capacity(args.names.len());
+    let mut failed = 0;
+    for name in &args.names {
+        match target(name, args.force) {
+            Ok(target) => targets.push(target),
+            Err(error) if args.force => {
+                eprintln!("Could not remove {name}: {error:#}");
+                failed += 1;
+            }
+            // Nothing is removed unless every toolbox can be
+            Err(error) => return Err(error),
+        }
+    }
+    if args.dry_run || !args.yes {
+        println!("The following toolboxes would be removed:");
+        for target in &targets {
+            let usage = Usage::of(Path::new(&target.config.image));
+            println!(
+                "\t{} ({}): {} {}",
+                target.name,
+                Size(usage.real),
+                target.config.image,
+                target.meta.display()
+            );
+            if !target.snapshots.is_empty() {
+                println!("\t\tand its snapshots {}", target.snapshots.join(", "));
+            }
+        }
+    }
+    if args.dry_run || targets.is_empty() {
+        return Ok(());
+    }
+    if !args.yes {
+        confirm(targets.len())?;
+    }
+    for target in targets {
+        match (remove_one(&target), args.force) {
+            (Ok(()), true) => println!("Removed {}", target.name),
+            (Ok(()), false) => {}
+            (Err(error), true) => {
+                eprintln!("Could not remove {}: {error:#}", target.name);
+                failed += 1;
+            }
+            (Err(error), false) => return Err(error),
+        }
     }
+    eyre::ensure!(failed == 0, "Could not remove {failed} of the toolboxes");
     Ok(())
 }
 
-pub fn remove_one(name: String) -> eyre::Result<()> {
-    let home = env::var("HOME").wrap_err("Could not find current home")?;
-    let meta = format!("{home}/{}/meta/{}.toml", STORAGE, name);
-    let config =
-        Config::read_or_new(&name).wrap_err("Could not get configuration for the toolbox")?;
-    for entry in WalkDir::new(&config.image)
-        .into_iter()
-        .filter_map(|e| e.ok())
-    {
-        let perms = Permissions::from_mode(0o777);
-        // We change the permissions on directories to avoid errors on read-only directories
-        if entry.file_type().is_dir() {
-            std::fs::set_permissions(entry.path(), perms).expect("we own the files");
-        }
+fn target(name: &str, force: bool) -> eyre::Result<Target> {
+    let config = Config::find(name)?;
+    if !force {
+        let running = !Session::live(name)?.is_empty();
+        eyre::ensure!(
+            !running,
+            "The toolbox {name} is still running, stop it with `unbox stop {name}` or use --force"
+        );
     }
+    Ok(Target {
+        name: name.to_string(),
+        meta: meta_path(name)?,
+        snapshots: snapshot_tags(name)?,
+        config,
+    })
+}
+
+fn confirm(count: usize) -> eyre::Result<()> {
+    let toolboxes = if count == 1 { "toolbox" } else { "toolboxes" };
+    print!("Remove {count} {toolboxes}? [y/N] ");
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin()
+        .read_line(&mut answer)
+        .wrap_err("Could not read the answer")?;
+    eyre::ensure!(
+        matches!(answer.trim(), "y" | "Y" | "yes"),
+        "The removal was not confirmed, use -y to remove without asking"
+    );
+    Ok(())
+}
+
+/// Removes a toolbox, the current process has to be inside of the namespace of `enter_user_namespace`
+fn remove_one(target: &Target) -> eyre::Result<()> {
+    let image = Path::new(&target.config.image);
+    if image.exists() {
+        remove_tree(image).wrap_err("Could not remove the image of the toolbox")?;
+    }
+    remove_snapshots(&target.name)?;
     // The error is ignored because if the file does not exist we do not need to remove it.
-    let _ = std::fs::remove_file(meta);
-    std::fs::remove_dir_all(config.image).wrap_err("Could not remove the selected toolbox")
+    let _ = std::fs::remove_file(&target.meta);
+    Ok(())
 }
@@ -7,14 +7,49 @@ use color_eyre::eyre;
 use color_eyre::eyre::WrapErr;
 use nix::sched::CloneFlags;
 use std::env;
-use std::ffi::OsString;
 
 use crate::config::Config;
-use crate::namespaces::{Mapping, Namespace};
+use crate::environment::{self, Variables};
+use crate::init;
+use crate::namespaces::{ExecError, Mapper, Namespace, UserNs, OLD_ROOT};
+use crate::network::{self, Network};
+use crate::session::Session;
+
+/// Exit code when unbox fails before the command could be executed
+pub const EXIT_FAILURE: i32 = 125;
+/// Exit code when the command exists but could not be executed
+pub const EXIT_NOT_EXECUTABLE: i32 = 126;
+/// Exit code when the command could not be found
+pub const EXIT_NOT_FOUND: i32 = 127;
 
 pub enum Execute {
     Run(Run),
     Enter(Enter),
+    Exec(Exec),
+}
+
+impl Execute {
+    fn name(&self) -> &str {
+        match self {
+            Execute::Enter(args) => &args.name,
+            Execute::Run(args) => &args.name,
+            Execute::Exec(args) => &args.name,
+        }
+    }
+
+    /// Namespaces asked for in the command line, on top of the configuration of the toolbox
+    fn isolation(&self, config: &Config) -> Isolation {
+        let (userns, pidns, network) = match self {
+            Execute::Enter(args) => (args.userns, args.pidns, args.network),
+            Execute::Run(args) => (args.userns, args.pidns, args.network),
+            Execute::Exec(_) => (None, false, None),
+        };
+        Isolation {
+            userns: userns.unwrap_or(config.userns),
+            pidns: pidns || config.pidns,
+            network: network.unwrap_or(config.network),
+        }
+    }
 }
 
 /// Enter a toolbox
@@ -23,6 +58,17 @@ pub struct Enter {
     #[clap(value_parser)]
     /// Name of the toolbox
     name: String,
+    #[clap(long, value_parser)]
+    /// How the ids of the user are mapped inside of the toolbox
+    userns: Option<UserNs>,
+    #[clap(long, value_parser)]
+    /// Isolate the processes of the toolbox in a new PID namespace
+    pidns: bool,
+    #[clap(long, value_parser)]
+    /// Network that the toolbox has access to
+    network: Option<Network>,
+    #[clap(flatten)]
+    variables: Variables,
 }
 
 /// Run a command in a toolbox
@@ -31,6 +77,17 @@ pub struct Run {
     #[clap(value_parser)]
     /// Name of the toolbox
     pub name: String,
+    #[clap(long, value_parser)]
+    /// How the ids of the user are mapped inside of the toolbox
+    pub userns: Option<UserNs>,
+    #[clap(long, value_parser)]
+    /// Isolate the processes of the toolbox in a new PID namespace
+    pub pidns: bool,
+    #[clap(long, value_parser)]
+    /// Network that the toolbox has access to
+    pub network: Option<Network>,
+    #[clap(flatten)]
+    pub variables: Variables,
     #[clap(value_parser)]
     /// Command to run
     pub cmd: String,
@@ -39,56 +96,184 @@ pub struct Run {
     pub args: Vec<String>,
 }
 
+/// Run a command in a running session of a toolbox, sharing its namespaces
+#[derive(Args, PartialEq, Eq, Debug)]
+pub struct Exec {
+    #[clap(value_parser)]
+    /// Name of the toolbox
+    pub name: String,
+    #[clap(flatten)]
+    pub variables: Variables,
+    #[clap(value_parser)]
+    /// Command to run, by default the shell of the toolbox
+    pub cmd: Option<String>,
+    /// Command arguments
+    #[clap(value_parser)]
+    pub args: Vec<String>,
+}
+
 pub fn nsexec(args: Execute) -> eyre::Result<()> {
-    let flags = CloneFlags::CLONE_NEWUSER | CloneFlags::CLONE_NEWUTS | CloneFlags::CLONE_NEWNS;
+    let config = configuration(&args)?;
+    let name = args.name().to_string();
+    let isolation = args.isolation(&config);
+    let sessions = Session::live(&name)?;
+    match args {
+        Execute::Exec(args) => match sessions.first() {
+            Some(session) => {
+                let cmd = args.cmd.unwrap_or_else(|| config.shell.clone());
+                join(&config, session, &args.variables, cmd, args.args)
+            }
+            None => eyre::bail!(
+                "The toolbox {name} has no running session, start one with `unbox enter {name}`"
+            ),
+        },
+        Execute::Enter(args) => {
+            // Entering a toolbox joins a running session with the same namespaces it asks for
+            match sessions.iter().find(|s| Isolation::from(*s) == isolation) {
+                Some(session) => join(
+                    &config,
+                    session,
+                    &args.variables,
+                    config.shell.clone(),
+                    Vec::new(),
+                ),
+                None => new_session(Execute::Enter(args), &name, config),
+            }
+        }
+        args => new_session(args, &name, config),
+    }
+}
+
+/// Namespaces of a session, that decide whether it can be joined
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+struct Isolation {
+    userns: UserNs,
+    pidns: bool,
+    network: Network,
+}
 
-    let uid = users::get_current_uid().to_string();
-    let gid = users::get_current_gid().to_string();
-    let pivot = Namespace::start(flags, &id_map(&uid), &id_map(&gid))?;
+impl From<&Session> for Isolation {
+    fn from(session: &Session) -> Self {
+        Isolation {
+            userns: session.userns,
+            pidns: session.pidns,
+            network: session.network,
+        }
+    }
+}
 
-    let config = configuration(&args)?;
-    let new_root = &config.image;
-    let old_root = format!("{new_root}/host");
-    let mounts = config.mounts().filter_map(|m| m.ok());
+fn new_session(args: Execute, name: &str, config: Config) -> eyre::Result<()> {
+    let mut flags = CloneFlags::CLONE_NEWUSER | CloneFlags::CLONE_NEWUTS | CloneFlags::CLONE_NEWNS;
+    let variables = match &args {
+        Execute::Enter(args) => args.variables.clone(),
+        Execute::Run(args) => args.variables.clone(),
+        Execute::Exec(_) => unreachable!("exec only joins running sessions"),
+    };
+    let Isolation {
+        userns,
+        pidns,
+        network,
+    } = args.isolation(&config);
+    if pidns {
+        flags |= CloneFlags::CLONE_NEWPID;
+    }
+    if network.isolated() {
+        flags |= CloneFlags::CLONE_NEWNET;
+    }
 
-    env::set_var("PATH", extend_path());
-    env::set_var("HOME", &config.home);
+    let uid = users::get_current_uid();
+    let gid = users::get_current_gid();
+    let identity = userns.identity();
+    let (subuids, subgids) = Mapper::detect().ranges()?;
+    let uid_mappings = userns.mappings(uid, &subuids);
+    let gid_mappings = userns.mappings(gid, &subgids);
+    let mut pivot = Namespace::start(flags, &uid_mappings, &gid_mappings)?;
+    pivot.wait();
+    let (cmd, args) = match args {
+        Execute::Enter(_) => (config.shell.clone(), Vec::new()),
+        Execute::Run(args) => (args.cmd, args.args),
+        Execute::Exec(_) => unreachable!("exec only joins running sessions"),
+    };
+    let command = command_line(&cmd, &args);
+    let session = Session::record(name, command, userns, pidns, network)?;
+
+    let new_root = &config.image;
+    let old_root = format!("{new_root}{OLD_ROOT}");
+    let mounts: Vec<_> = config
+        .mounts()
+        .filter_map(|m| m.ok())
+        // Name servers of the host are not reachable without its network
+        .filter(|m| !network.isolated() || m.target != "/etc/resolv.conf")
+        .collect();
+    let cwd = env::current_dir().ok();
+    environment::apply(&config, &variables)?;
 
+    let init = match pidns {
+        true => Some(pivot.spawn_init(&cmd, &args, identity)?),
+        false => None,
+    };
     let mut toolbox = pivot.pivot(new_root.as_ref(), old_root.as_ref())?;
-    toolbox.mounts(mounts)?;
+    toolbox.mounts(mounts.iter().cloned())?;
     toolbox.hostname(&config.hostname)?;
-    match args {
-        Execute::Enter(_) => toolbox.spawn(config.shell, &[]),
-        Execute::Run(args) => toolbox.spawn(args.cmd, &args.args),
+    if network == Network::Loopback {
+        network::loopback_up()?;
+    }
+    let workdir = toolbox.workdir(cwd.as_deref(), &mounts, &config.home);
+    if let Some(init) = init {
+        let exit = init.start(&workdir);
+        // The runtime directory of the host is only reachable through the old root after pivoting
+        session.forget(OLD_ROOT.as_ref())?;
+        exit?.exit();
     }
+    toolbox.spawn(cmd, &args, identity)
 }
 
-fn id_map(guid: &str) -> [Mapping<'_>; 2] {
-    [
-        Mapping {
-            inside: "0",
-            outside: guid,
-            len: "1",
-        },
-        Mapping {
-            inside: "1",
-            outside: "100000",
-            len: "65536",
-        },
-    ]
+fn join(
+    config: &Config,
+    session: &Session,
+    variables: &Variables,
+    cmd: String,
+    args: Vec<String>,
+) -> eyre::Result<()> {
+    let mounts: Vec<_> = config.mounts().filter_map(|m| m.ok()).collect();
+    let cwd = env::current_dir().ok();
+    let identity = session.userns.identity();
+    // Joined processes keep the toolbox in use even after the session they joined finishes
+    let joined = Session::record(
+        &session.name,
+        command_line(&cmd, &args),
+        session.userns,
+        session.pidns,
+        session.network,
+    )?;
+    environment::apply(config, variables)?;
+
+    let mut toolbox = Namespace::join(session.pid)?;
+    toolbox.workdir(cwd.as_deref(), &mounts, &config.home);
+    if session.pidns {
+        let exit = init::spawn_and_wait(cmd, &args, identity);
+        joined.forget(OLD_ROOT.as_ref())?;
+        exit?.exit();
+    }
+    toolbox.spawn(cmd, &args, identity)
 }
 
 fn configuration(args: &Execute) -> eyre::Result<Config> {
-    let name = match args {
-        Execute::Enter(args) => &args.name,
-        Execute::Run(args) => &args.name,
-    };
-    Config::read_or_new(name).wrap_err("Could not get configuration for the toolbox")
+    Config::read_or_new(args.name()).wrap_err("Could not get configuration for the toolbox")
 }
 
-fn extend_path() -> OsString {
-    let mut path = env::var_os("PATH").expect("PATH needs to exist");
-    path.push(":/bin");
-    path.push(":/sbin");
-    path
+fn command_line(cmd: &str, args: &[String]) -> String {
+    std::iter::once(cmd)
+        .chain(args.iter().map(String::as_str))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Exit code that corresponds to an error of `run` or `enter`
+pub fn exit_code(error: &eyre::Report) -> i32 {
+    match error.chain().find_map(|e| e.downcast_ref::<ExecError>()) {
+        Some(exec) if exec.not_executable() => EXIT_NOT_EXECUTABLE,
+        Some(_) => EXIT_NOT_FOUND,
+        None => EXIT_FAILURE,
+    }
 }
@@ -0,0 +1,170 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::env;
+use std::fs::{create_dir_all, read_dir, symlink_metadata, DirBuilder};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use color_eyre::eyre;
+use color_eyre::eyre::WrapErr;
+use serde::{Deserialize, Serialize};
+
+use crate::namespaces::UserNs;
+use crate::network::Network;
+
+/// Running instance of a toolbox whose namespaces can be joined
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Session {
+    /// Name of the toolbox
+    pub name: String,
+    /// Process that stays inside of the namespaces of the session
+    pub pid: u32,
+    /// Start time of the process in clock ticks since boot, tells it apart from a reused pid
+    pub started: u64,
+    /// Seconds since the Unix epoch when the session started
+    pub since: u64,
+    /// Command that the session runs
+    pub command: String,
+    pub userns: UserNs,
+    pub pidns: bool,
+    // Sessions recorded before the network could be chosen shared the one of the host
+    #[serde(default)]
+    pub network: Network,
+    #[serde(skip)]
+    record: PathBuf,
+}
+
+impl Session {
+    /// Records the current process as a session of the toolbox
+    pub fn record(
+        name: &str,
+        command: String,
+        userns: UserNs,
+        pidns: bool,
+        network: Network,
+    ) -> eyre::Result<Self> {
+        let pid = std::process::id();
+        let dir = directory(name)?;
+        let since = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let session = Session {
+            name: name.to_string(),
+            pid,
+            started: start_time(pid).wrap_err("Could not find the start time of the session")?,
+            since,
+            command,
+            userns,
+            pidns,
+            network,
+            record: dir.join(format!("{pid}.toml")),
+        };
+        create_dir_all(&dir).wrap_err("Could not create sessions directory")?;
+        let content = toml::to_string(&session).expect("valid toml session");
+        std::fs::write(&session.record, content).wrap_err("Could not record the session")?;
+        Ok(session)
+    }
+
+    /// Sessions of the toolbox that are still running, oldest first. Finished ones are forgotten
+    pub fn live(name: &str) -> eyre::Result<Vec<Self>> {
+        let dir = directory(name)?;
+        let entries = match read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => return Ok(Vec::new()),
+        };
+        let mut sessions = Vec::new();
+        for entry in entries.filter_map(|e| e.ok()) {
+            let session = std::fs::read_to_string(entry.path())
+                .ok()
+                .and_then(|content| toml::from_str::<Session>(&content).ok());
+            match session {
+                Some(session) if session.alive() => sessions.push(session),
+                _ => {
+                    let _ = std::fs::remove_file(entry.path());
+                }
+            }
+        }
+        sessions.sort_by_key(|s| s.started);
+        Ok(sessions)
+    }
+
+    /// Running sessions of every toolbox
+    pub fn all() -> eyre::Result<Vec<Self>> {
+        let entries = match read_dir(runtime_dir()?.join("sessions")) {
+            Ok(entries) => entries,
+            Err(_) => return Ok(Vec::new()),
+        };
+        let mut sessions = Vec::new();
+        for name in entries.filter_map(|e| e.ok()?.file_name().into_string().ok()) {
+            sessions.extend(Session::live(&name)?);
+        }
+        sessions.sort_by(|a, b| (&a.name, a.started).cmp(&(&b.name, b.started)));
+        Ok(sessions)
+    }
+
+    pub fn alive(&self) -> bool {
+        start_time(self.pid).ok() == Some(self.started)
+    }
+
+    /// Removes the record of a session that has finished, with the root of the host found at `root`
+    pub fn forget(&self, root: &Path) -> eyre::Result<()> {
+        let record = root.join(self.record.strip_prefix("/").unwrap_or(&self.record));
+        std::fs::remove_file(record).wrap_err("Could not remove the record of the session")
+    }
+}
+
+fn directory(name: &str) -> eyre::Result<PathBuf> {
+    Ok(runtime_dir()?.join("sessions").join(name))
+}
+
+/// Directory for files that only make sense while the system is running
+fn runtime_dir() -> eyre::Result<PathBuf> {
+    if let Some(dir) = env::var_os("XDG_RUNTIME_DIR") {
+        return Ok(PathBuf::from(dir).join("unbox"));
+    }
+    // Unlike the runtime directory of the user a temporary one is shared, so it has to be private
+    let uid = users::get_current_uid();
+    let dir = env::temp_dir().join(format!("unbox-{}", host_uid(uid)));
+    match DirBuilder::new().mode(0o700).create(&dir) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(e).wrap_err("Could not create the runtime directory"),
+    }
+    let meta = symlink_metadata(&dir).wrap_err("Could not read the runtime directory")?;
+    eyre::ensure!(
+        meta.is_dir() && meta.uid() == uid,
+        "The runtime directory {dir:?} belongs to another user"
+    );
+    Ok(dir)
+}
+
+/// Uid outside of the user namespace that corresponds to `uid`, the same from every namespace of unbox
+fn host_uid(uid: u32) -> u32 {
+    let map = std::fs::read_to_string("/proc/self/uid_map").unwrap_or_default();
+    map.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace().map(|f| f.parse::<u32>().ok());
+            Some((fields.next()??, fields.next()??, fields.next()??))
+        })
+        .find(|(inside, _, len)| uid >= *inside && uid - inside < *len)
+        .map(|(inside, outside, _)| outside + (uid - inside))
+        .unwrap_or(uid)
+}
+
+fn start_time(pid: u32) -> eyre::Result<u64> {
+    let stat = std::fs::read_to_string(format!("/proc/{pid}/stat"))?;
+    // The name of the command can contain spaces, the fields after it cannot
+    let fields = stat
+        .rsplit_once(')')
+        .map(|(_, fields)| fields)
+        .unwrap_or_default();
+    let started = fields
+        .split_whitespace()
+        .nth(19)
+        .ok_or_else(|| eyre::eyre!("Invalid stat file for process {pid}"))?;
+    Ok(started.parse()?)
+}
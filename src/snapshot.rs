@@ -0,0 +1,253 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use clap::Args;
+use color_eyre::eyre;
+use color_eyre::eyre::WrapErr;
+use nix::fcntl::{renameat2, RenameFlags};
+use serde::{Deserialize, Serialize};
+use tabled::{Style, Table, Tabled};
+
+use crate::config::{storage, Config};
+use crate::copy::{copy_tree, remove_tree, Method, INDEPENDENT};
+use crate::namespaces::enter_user_namespace;
+use crate::session::Session;
+
+/// Directory of a snapshot with the copy of the root filesystem
+const ROOTFS: &str = "rootfs";
+/// File of a snapshot with how and when it was taken
+const RECORD: &str = "snapshot.toml";
+/// Ways to copy a root filesystem, a full copy is only used when the others fail
+const METHODS: [Method; 4] = [
+    Method::Subvolume,
+    Method::Reflink,
+    Method::Hardlink,
+    Method::Copy,
+];
+
+/// Save the root filesystem of a toolbox to restore it later
+#[derive(Args, PartialEq, Eq, Debug)]
+pub struct Snapshot {
+    #[clap(short, long, value_parser)]
+    /// List the snapshots, only the ones of the toolbox if a name is given
+    pub list: bool,
+    #[clap(value_parser, required_unless_present = "list")]
+    /// Name of the toolbox
+    pub name: Option<String>,
+    #[clap(value_parser = parse_tag, conflicts_with = "list")]
+    /// Tag of the snapshot, the current date and time by default
+    pub tag: Option<String>,
+}
+
+/// Restore the root filesystem of a toolbox from a snapshot
+#[derive(Args, PartialEq, Eq, Debug)]
+pub struct Rollback {
+    #[clap(value_parser)]
+    /// Name of the toolbox
+    pub name: String,
+    #[clap(value_parser = parse_tag)]
+    /// Tag of the snapshot
+    pub tag: String,
+}
+
+/// How and when a snapshot was taken
+#[derive(Debug, Deserialize, Serialize)]
+struct Record {
+    created: u64,
+    method: Method,
+}
+
+struct Row {
+    name: String,
+    tag: String,
+    record: Option<Record>,
+}
+
+impl Tabled for Row {
+    const LENGTH: usize = 4;
+
+    fn fields(&self) -> Vec<Cow<'_, str>> {
+        let created = self.record.as_ref().map(|r| {
+            let created = UNIX_EPOCH + Duration::from_secs(r.created);
+            humantime::format_rfc3339_seconds(created).to_string()
+        });
+        let method = self.record.as_ref().map(|r| r.method.to_string());
+        vec![
+            Cow::Borrowed(&self.name),
+            Cow::Borrowed(&self.tag),
+            Cow::Owned(created.unwrap_or_else(|| "-".into())),
+            Cow::Owned(method.unwrap_or_else(|| "-".into())),
+        ]
+    }
+    fn headers() -> Vec<Cow<'static, str>> {
+        ["name", "tag", "created", "method"]
+            .into_iter()
+            .map(Cow::from)
+            .collect()
+    }
+}
+
+/// A tag names a directory, so it cannot be a path
+fn parse_tag(tag: &str) -> Result<String, String> {
+    if tag.is_empty() || tag.starts_with('.') || tag.contains('/') {
+        return Err("expected a name that does not start with a dot or contain a slash".into());
+    }
+    Ok(tag.to_string())
+}
+
+/// Directory with the snapshots of a toolbox
+pub fn snapshots_path(name: &str) -> eyre::Result<PathBuf> {
+    Ok(storage()?.join("snapshots").join(name))
+}
+
+pub fn snapshot(args: Snapshot) -> eyre::Result<()> {
+    if args.list {
+        return list(args.name);
+    }
+    let name = args.name.expect("clap requires a name without --list");
+    let config = Config::find(&name)?;
+    let image = Path::new(&config.image);
+    eyre::ensure!(image.is_dir(), "The image of {name} does not exist");
+    // Files being written by the toolbox would be saved half written
+    eyre::ensure!(
+        Session::live(&name)?.is_empty(),
+        "The toolbox {name} is still running, stop it with `unbox stop {name}` first"
+    );
+    let now = SystemTime::now();
+    let tag = match args.tag {
+        Some(tag) => tag,
+        None => humantime::format_rfc3339_seconds(now).to_string(),
+    };
+    let dir = snapshots_path(&name)?.join(&tag);
+    eyre::ensure!(!dir.exists(), "There is already a snapshot {tag} of {name}");
+    // Files of the subordinate ids are copied with their owners
+    enter_user_namespace()?;
+    std::fs::create_dir_all(&dir).wrap_err("Could not create the snapshot directory")?;
+    let method = match copy_tree(image, &dir.join(ROOTFS), &METHODS) {
+        Ok(method) => method,
+        Err(error) => {
+            // The error is ignored because the snapshot could not be taken anyway
+            let _ = std::fs::remove_dir_all(&dir);
+            return Err(error);
+        }
+    };
+    let record = Record {
+        created: now
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default(),
+        method,
+    };
+    let content = toml::to_string(&record).expect("valid toml record");
+    std::fs::write(dir.join(RECORD), content).wrap_err("Could not write the snapshot record")?;
+    println!("Saved snapshot {tag} of {name} ({method})");
+    if method == Method::Hardlink {
+        eprintln!("Warning: the snapshot shares its files with the toolbox, so a file that is");
+        eprintln!("modified in place instead of being replaced changes in both of them");
+    }
+    Ok(())
+}
+
+pub fn rollback(args: Rollback) -> eyre::Result<()> {
+    let Rollback { name, tag } = args;
+    let config = Config::find(&name)?;
+    let snapshot = snapshots_path(&name)?.join(&tag).join(ROOTFS);
+    eyre::ensure!(snapshot.is_dir(), "There is no snapshot {tag} of {name}");
+    eyre::ensure!(
+        Session::live(&name)?.is_empty(),
+        "The toolbox {name} is still running, stop it with `unbox stop {name}` first"
+    );
+    let image = PathBuf::from(&config.image);
+    let file_name = image
+        .file_name()
+        .ok_or_else(|| eyre::eyre!("The image of {name} is not a directory"))?;
+    // The copy has to be next to the image, in the same filesystem, to be swapped with it
+    let restored = image.with_file_name(format!(".{}-rollback", file_name.to_string_lossy()));
+    enter_user_namespace()?;
+    if restored.exists() {
+        // Leftover of an interrupted rollback
+        remove_tree(&restored)?;
+    }
+    // The snapshot is copied without sharing its files so that it can be restored again
+    copy_tree(&snapshot, &restored, &INDEPENDENT)?;
+    if image.exists() {
+        renameat2(None, &restored, None, &image, RenameFlags::RENAME_EXCHANGE)
+            .wrap_err("Could not swap the image with the snapshot")?;
+        remove_tree(&restored).wrap_err("Could not remove the previous image")?;
+    } else {
+        std::fs::rename(&restored, &image).wrap_err("Could not restore the image")?;
+    }
+    println!("Rolled back {name} to {tag}");
+    Ok(())
+}
+
+/// Tags of the snapshots of a toolbox, sorted by name
+pub fn snapshot_tags(name: &str) -> eyre::Result<Vec<String>> {
+    let mut tags: Vec<String> = std::fs::read_dir(snapshots_path(name)?)
+        .into_iter()
+        .flatten()
+        .filter_map(|p| p.ok()?.file_name().into_string().ok())
+        .collect();
+    tags.sort_unstable();
+    Ok(tags)
+}
+
+/// Removes every snapshot of a toolbox, the current process has to be inside of the namespace of
+/// `enter_user_namespace`
+pub fn remove_snapshots(name: &str) -> eyre::Result<()> {
+    let dir = snapshots_path(name)?;
+    if !dir.exists() {
+        return Ok(());
+    }
+    for tag in std::fs::read_dir(&dir).wrap_err("Could not read the snapshots")? {
+        let rootfs = tag?.path().join(ROOTFS);
+        if rootfs.exists() {
+            remove_tree(&rootfs)?;
+        }
+    }
+    std::fs::remove_dir_all(dir).wrap_err("Could not remove the snapshots")
+}
+
+fn list(name: Option<String>) -> eyre::Result<()> {
+    let dir = storage()?.join("snapshots");
+    let mut names: Vec<String> = match name {
+        Some(name) => vec![name],
+        None => std::fs::read_dir(&dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|p| p.ok()?.file_name().into_string().ok())
+            .collect(),
+    };
+    names.sort_unstable();
+    let mut rows = Vec::new();
+    for name in names {
+        let mut snapshots: Vec<Row> = snapshot_tags(&name)?
+            .into_iter()
+            .map(|tag| {
+                let record = std::fs::read_to_string(dir.join(&name).join(&tag).join(RECORD))
+                    .ok()
+                    .and_then(|r| toml::from_str(&r).ok());
+                Row {
+                    name: name.clone(),
+                    tag,
+                    record,
+                }
+            })
+            .collect();
+        snapshots.sort_by_key(|row| row.record.as_ref().map(|r| r.created));
+        rows.extend(snapshots);
+    }
+    if rows.is_empty() {
+        println!("There are no snapshots");
+    } else {
+        let mut table = Table::new(rows);
+        let table = table.with(Style::modern());
+        print!("{table}");
+    }
+    Ok(())
+}
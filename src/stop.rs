@@ -0,0 +1,46 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use clap::Args;
+use color_eyre::eyre;
+use color_eyre::eyre::WrapErr;
+use nix::sys::signal::{kill, Signal};
+use nix::unistd::Pid;
+
+use crate::session::Session;
+
+/// Stop the running sessions of a toolbox
+#[derive(Args, PartialEq, Eq, Debug)]
+pub struct Stop {
+    #[clap(value_parser)]
+    /// Names of the toolboxes to be stopped
+    pub names: Vec<String>,
+    #[clap(short, long, value_parser = parse_signal, default_value = "TERM")]
+    /// Signal sent to the sessions, by name (TERM, SIGKILL) or number
+    pub signal: Signal,
+}
+
+pub fn stop(args: Stop) -> eyre::Result<()> {
+    for name in args.names {
+        let sessions = Session::live(&name)?;
+        if sessions.is_empty() {
+            eprintln!("Notice: the toolbox {name} is not running");
+        }
+        for session in sessions {
+            kill(Pid::from_raw(session.pid as i32), args.signal)
+                .wrap_err(format!("Could not stop session {} of {name}", session.pid))?;
+        }
+    }
+    Ok(())
+}
+
+fn parse_signal(signal: &str) -> Result<Signal, String> {
+    let signal = signal.to_uppercase();
+    let parsed = match signal.parse::<i32>() {
+        Ok(number) => Signal::try_from(number),
+        Err(_) if signal.starts_with("SIG") => signal.parse(),
+        Err(_) => format!("SIG{signal}").parse(),
+    };
+    parsed.map_err(|_| format!("unknown signal {signal}"))
+}
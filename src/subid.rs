@@ -0,0 +1,71 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::path::Path;
+
+use color_eyre::eyre;
+use color_eyre::eyre::WrapErr;
+
+pub const SUBUID: &str = "/etc/subuid";
+pub const SUBGID: &str = "/etc/subgid";
+
+/// Range of subordinate ids delegated to a user
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct SubIds {
+    pub start: u32,
+    pub count: u32,
+}
+
+/// Reads the subordinate uids of the current user
+pub fn subuids() -> eyre::Result<Vec<SubIds>> {
+    read(
+        Path::new(SUBUID),
+        &current_username()?,
+        users::get_current_uid(),
+    )
+}
+
+/// Reads the subordinate gids of the current user
+pub fn subgids() -> eyre::Result<Vec<SubIds>> {
+    read(
+        Path::new(SUBGID),
+        &current_username()?,
+        users::get_current_uid(),
+    )
+}
+
+fn current_username() -> eyre::Result<String> {
+    users::get_current_username()
+        .and_then(|name| name.into_string().ok())
+        .ok_or_else(|| eyre::eyre!("Could not find the current user"))
+}
+
+/// Reads the ranges of a user from a subordinate ids file, failing if there are none
+pub fn read(path: &Path, name: &str, uid: u32) -> eyre::Result<Vec<SubIds>> {
+    let content = std::fs::read_to_string(path).wrap_err(format!("Could not read {path:?}"))?;
+    let ranges = parse(&content, name, uid);
+    eyre::ensure!(
+        !ranges.is_empty(),
+        "No subordinate ids are configured for {name} in {path:?}, add a line like `{name}:100000:65536`"
+    );
+    Ok(ranges)
+}
+
+/// Finds every range that belongs to the user, either by name or by uid
+pub fn parse(content: &str, name: &str, uid: u32) -> Vec<SubIds> {
+    let uid = uid.to_string();
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut fields = line.split(':');
+            let owner = fields.next()?;
+            let start = fields.next()?.parse().ok()?;
+            let count = fields.next()?.parse().ok()?;
+            (owner == name || owner == uid).then_some(SubIds { start, count })
+        })
+        .filter(|range| range.count > 0)
+        .collect()
+}
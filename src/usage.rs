@@ -0,0 +1,66 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+use serde::Serialize;
+use walkdir::WalkDir;
+
+/// Disk usage of a directory tree, counting every hardlinked file once
+#[derive(Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize)]
+pub struct Usage {
+    /// Sum of the sizes of the files, like `du --apparent-size`
+    pub apparent: u64,
+    /// Space allocated on the disk, like `du`
+    pub real: u64,
+}
+
+impl Usage {
+    /// Walks the tree without following symlinks or crossing into other filesystems. Entries
+    /// that cannot be read, like directories of other users of the toolbox, are skipped
+    pub fn of(root: &Path) -> Self {
+        let mut seen = HashSet::new();
+        let mut usage = Usage::default();
+        let entries = WalkDir::new(root)
+            .same_file_system(true)
+            .into_iter()
+            .filter_map(|e| e.ok());
+        for entry in entries {
+            let meta = match entry.metadata() {
+                Ok(meta) => meta,
+                Err(_) => continue,
+            };
+            if meta.nlink() > 1 && !meta.is_dir() && !seen.insert((meta.dev(), meta.ino())) {
+                continue;
+            }
+            usage.apparent += meta.size();
+            // st_blocks is always in units of 512 bytes, whatever the block size of the filesystem
+            usage.real += meta.blocks() * 512;
+        }
+        usage
+    }
+}
+
+/// Amount of bytes formatted with binary prefixes, e.g. `1.5 GiB`
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Size(pub u64);
+
+impl Display for Size {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+        if self.0 < 1024 {
+            return write!(f, "{} B", self.0);
+        }
+        let mut size = self.0 as f64 / 1024.0;
+        let mut unit = 0;
+        while size >= 1024.0 && unit < UNITS.len() - 1 {
+            size /= 1024.0;
+            unit += 1;
+        }
+        write!(f, "{size:.1} {}", UNITS[unit])
+    }
+}
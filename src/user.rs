@@ -0,0 +1,158 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::fs::create_dir_all;
+use std::path::Path;
+
+use color_eyre::eyre;
+use color_eyre::eyre::WrapErr;
+
+use crate::config::Config;
+
+/// Groups that usually grant administrative rights, in order of preference
+const ADMIN_GROUPS: [&str; 2] = ["wheel", "sudo"];
+
+/// Account of the invoking user that is replicated inside of the toolbox
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct User {
+    pub name: String,
+    pub uid: u32,
+    pub group: String,
+    pub gid: u32,
+    pub home: String,
+    pub shell: String,
+}
+
+impl User {
+    /// Gets the current user, it has to be called before entering any user namespace
+    pub fn current(config: &Config) -> eyre::Result<Self> {
+        let name = users::get_current_username()
+            .ok_or_else(|| eyre::eyre!("Could not find the current user"))?;
+        let group = users::get_current_groupname()
+            .ok_or_else(|| eyre::eyre!("Could not find the current group"))?;
+        Ok(User {
+            name: name.into_string().expect("Username is valid UTF8"),
+            uid: users::get_current_uid(),
+            group: group.into_string().expect("Group name is valid UTF8"),
+            gid: users::get_current_gid(),
+            home: config.home.clone(),
+            shell: config.shell.clone(),
+        })
+    }
+
+    /// Adds the user to the account databases of the root filesystem and creates its home
+    pub fn create(&self, root: &Path, admin: bool) -> eyre::Result<()> {
+        // Every image already has a root user
+        if self.uid == 0 {
+            return Ok(());
+        }
+        let etc = root.join("etc");
+        let mut replaced = Vec::new();
+        edit(&etc.join("passwd"), |passwd| {
+            replaced = self.replaced(passwd);
+            self.passwd(passwd)
+        })?;
+        edit(&etc.join("group"), |group| {
+            self.groups(group, &replaced, admin)
+        })?;
+        let shadow = etc.join("shadow");
+        if shadow.exists() {
+            edit(&shadow, |shadow| self.shadow(shadow, &replaced))?;
+        }
+        let home = root.join(self.home.trim_start_matches('/'));
+        create_dir_all(home).wrap_err("Could not create the home directory")
+    }
+
+    /// Accounts of the image with the uid of the user, like the default user of some images, which
+    /// are replaced by it
+    fn replaced(&self, passwd: &str) -> Vec<String> {
+        let uid = self.uid.to_string();
+        passwd
+            .lines()
+            .filter(|l| field(l, 2) == Some(&uid) && field(l, 0) != Some(&self.name))
+            .filter_map(|l| field(l, 0))
+            .map(String::from)
+            .collect()
+    }
+
+    fn passwd(&self, passwd: &str) -> String {
+        let uid = self.uid.to_string();
+        let mut content: String = passwd
+            .lines()
+            .filter(|l| field(l, 0) != Some(&self.name) && field(l, 2) != Some(&uid))
+            .flat_map(|l| [l, "\n"])
+            .collect();
+        content.push_str(&format!(
+            "{name}:x:{uid}:{gid}:{name}:{home}:{shell}\n",
+            name = self.name,
+            gid = self.gid,
+            home = self.home,
+            shell = self.shell,
+        ));
+        content
+    }
+
+    fn groups(&self, group: &str, replaced: &[String], admin: bool) -> String {
+        let gid = self.gid.to_string();
+        let replaced_by = |name: Option<&str>| replaced.iter().any(|r| Some(r.as_str()) == name);
+        // The private group of a replaced account would take the gid of the user
+        let group: String = group
+            .lines()
+            .filter(|l| !(replaced_by(field(l, 0)) && field(l, 2) == Some(&gid)))
+            .flat_map(|l| [l, "\n"])
+            .collect();
+        let admin_group = ADMIN_GROUPS
+            .into_iter()
+            .find(|g| group.lines().any(|l| field(l, 0) == Some(g)))
+            .filter(|_| admin);
+        let mut content = String::with_capacity(group.len() + 64);
+        for line in group.lines() {
+            let Some(members) = field(line, 3) else {
+                content.push_str(line);
+                content.push('\n');
+                continue;
+            };
+            let mut members: Vec<&str> = members
+                .split(',')
+                .filter(|m| !m.is_empty() && !replaced_by(Some(m)))
+                .collect();
+            if field(line, 0) == admin_group && !members.contains(&self.name.as_str()) {
+                members.push(&self.name);
+            }
+            let (entry, _) = line.rsplit_once(':').expect("the line has members");
+            content.push_str(&format!("{entry}:{}\n", members.join(",")));
+        }
+        let exists = group
+            .lines()
+            .any(|l| field(l, 0) == Some(&self.group) || field(l, 2) == Some(&gid));
+        if !exists {
+            content.push_str(&format!("{}:x:{gid}:\n", self.group));
+        }
+        content
+    }
+
+    fn shadow(&self, shadow: &str, replaced: &[String]) -> String {
+        let mut content: String = shadow
+            .lines()
+            .filter(|l| field(l, 0) != Some(&self.name))
+            .filter(|l| !replaced.iter().any(|r| field(l, 0) == Some(r)))
+            .flat_map(|l| [l, "\n"])
+            .collect();
+        content.push_str(&format!("{}:!::0:99999:7:::\n", self.name));
+        content
+    }
+}
+
+fn field(line: &str, index: usize) -> Option<&str> {
+    line.split(':').nth(index)
+}
+
+fn edit<F>(path: &Path, change: F) -> eyre::Result<()>
+where
+    F: FnOnce(&str) -> String,
+{
+    // Images without the file still get the entry, e.g. when built from scratch
+    let content = std::fs::read_to_string(path).unwrap_or_default();
+    std::fs::write(path, change(&content)).wrap_err(format!("Could not update {path:?}"))
+}
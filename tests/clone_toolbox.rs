@@ -0,0 +1,49 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+mod common;
+
+use std::os::unix::fs::MetadataExt;
+
+use common::{add_tool, check_tool, Storage};
+
+#[test]
+fn clone_toolbox() {
+    let storage = Storage::new("clone");
+    let image = storage.toolbox("base", &["--hostname", "base-host", "-e", "EDITOR=vi"]);
+    add_tool(&image);
+
+    let status = storage
+        .unbox()
+        .args(["clone", "base", "variant"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let copy = storage.image("variant");
+    check_tool(&copy);
+    let original = std::fs::metadata(image.join("usr/bin/tool")).unwrap();
+    let tool = std::fs::metadata(copy.join("usr/bin/tool")).unwrap();
+    assert_ne!(original.ino(), tool.ino());
+    let meta = std::fs::read_to_string(storage.meta("variant")).unwrap();
+    assert!(!meta.contains("base-host"), "{meta}");
+    assert!(meta.contains("EDITOR = \"vi\""), "{meta}");
+    assert!(
+        meta.contains(&copy.to_string_lossy().into_owned()),
+        "{meta}"
+    );
+
+    let output = storage
+        .unbox()
+        .args(["clone", "base", "variant"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let output = storage
+        .unbox()
+        .args(["clone", "missing", "other"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(!storage.image("other").exists());
+}
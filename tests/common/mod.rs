@@ -0,0 +1,113 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Every test crate only uses some of the helpers
+#![allow(dead_code)]
+
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Owner given to files of an image when the tests run as root
+pub const OWNER: u32 = 1234;
+
+/// Empty storage and configuration in a temporary directory, removed when dropped
+pub struct Storage {
+    pub dir: PathBuf,
+}
+
+impl Storage {
+    pub fn new(test: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("unbox-{test}-test-{}", std::process::id()));
+        Storage { dir }
+    }
+
+    /// The unbox binary using this storage
+    pub fn unbox(&self) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_unbox"));
+        command
+            .env("UNBOX_STORAGE", &self.dir)
+            .env("XDG_CONFIG_HOME", self.dir.join("config"));
+        command
+    }
+
+    pub fn image(&self, name: &str) -> PathBuf {
+        self.dir.join("images").join(name)
+    }
+
+    pub fn meta(&self, name: &str) -> PathBuf {
+        self.dir.join("meta").join(format!("{name}.toml"))
+    }
+
+    /// Configures a toolbox with the extra arguments and creates its image with a single file
+    pub fn toolbox(&self, name: &str, args: &[&str]) -> PathBuf {
+        let status = self
+            .unbox()
+            .args(["configure", name])
+            .args(args)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let image = self.image(name);
+        std::fs::create_dir_all(image.join("etc")).unwrap();
+        std::fs::write(image.join("etc/file"), "content").unwrap();
+        image
+    }
+
+    /// Copies the `exec-tests` toolbox of the default storage, for tests that have to run commands
+    pub fn fixture(&self, name: &str) -> PathBuf {
+        std::fs::create_dir_all(&self.dir).unwrap();
+        let archive = self.dir.join("exec-tests.tar");
+        let status = Command::new(env!("CARGO_BIN_EXE_unbox"))
+            .args(["export", "exec-tests", "-o"])
+            .arg(&archive)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let status = self
+            .unbox()
+            .arg("import")
+            .arg(&archive)
+            .arg(name)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        std::fs::remove_file(archive).unwrap();
+        self.image(name)
+    }
+}
+
+impl Drop for Storage {
+    fn drop(&mut self) {
+        // The error is ignored so that a failed assertion is not hidden by a second panic
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Adds a setuid file with a hardlink and a symlink to its directory, owned by `OWNER` as root
+pub fn add_tool(image: &Path) {
+    std::fs::create_dir_all(image.join("usr/bin")).unwrap();
+    let tool = image.join("usr/bin/tool");
+    std::fs::write(&tool, "#!/bin/sh\n").unwrap();
+    if users::get_current_uid() == 0 {
+        std::os::unix::fs::chown(&tool, Some(OWNER), Some(OWNER)).unwrap();
+    }
+    // The owner is changed first because that drops the setuid bit
+    std::fs::set_permissions(&tool, std::fs::Permissions::from_mode(0o4755)).unwrap();
+    std::fs::hard_link(&tool, image.join("usr/bin/alias")).unwrap();
+    std::os::unix::fs::symlink("usr/bin", image.join("bin")).unwrap();
+}
+
+/// Checks that a copy of an image made with `add_tool` kept every property of its files
+pub fn check_tool(copy: &Path) {
+    let tool = std::fs::metadata(copy.join("usr/bin/tool")).unwrap();
+    let alias = std::fs::metadata(copy.join("usr/bin/alias")).unwrap();
+    assert_eq!(tool.ino(), alias.ino());
+    assert_eq!(tool.mode() & 0o7777, 0o4755);
+    if users::get_current_uid() == 0 {
+        assert_eq!((tool.uid(), tool.gid()), (OWNER, OWNER));
+    }
+    let link = std::fs::read_link(copy.join("bin")).unwrap();
+    assert_eq!(link, Path::new("usr/bin"));
+}
@@ -0,0 +1,51 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use unbox::config::*;
+use unbox::network::Network;
+
+#[test]
+fn global_defaults_beneath_meta() {
+    let dir = std::env::temp_dir().join(format!("unbox-config-test-{}", std::process::id()));
+    let global = dir.join("config/unbox/config.toml");
+    std::fs::create_dir_all(global.parent().unwrap()).unwrap();
+    std::env::set_var("HOME", &dir);
+    std::env::set_var("XDG_CONFIG_HOME", dir.join("config"));
+    std::env::set_var("SHELL", "/bin/sh");
+    std::env::remove_var("UNBOX_STORAGE");
+    std::env::remove_var("XDG_DATA_HOME");
+    setup().unwrap();
+    std::fs::write(&global, "shell = \"/bin/zsh\"\nnetwork = \"none\"\n").unwrap();
+
+    let mut config = Config::new("layers").unwrap();
+    assert_eq!(config.shell, "/bin/zsh");
+    assert_eq!(config.network, Network::None);
+    config.hostname = "custom".into();
+    config.write("layers").unwrap();
+    let meta = std::fs::read_to_string(dir.join(".local/share/unbox/meta/layers.toml")).unwrap();
+    assert!(meta.contains("hostname = \"custom\""));
+    assert!(!meta.contains("shell"));
+
+    // Existing toolboxes follow the global defaults they do not override
+    std::fs::write(&global, "shell = \"/bin/fish\"\n").unwrap();
+    let config = Config::read("layers").unwrap();
+    assert_eq!(config.shell, "/bin/fish");
+    assert_eq!(config.hostname, "custom");
+    assert_eq!(config.network, Network::Host);
+    let shown = config.show();
+    assert!(shown.contains(&format!(
+        "shell = \"/bin/fish\"  # global config {global:?}"
+    )));
+    assert!(shown.contains("\"/proc\" = \"/host/proc\"  # default"));
+
+    // The shell and home taken from the environment are kept even when it changes
+    std::fs::write(&global, "").unwrap();
+    Config::new("pinned").unwrap().write("pinned").unwrap();
+    let meta = std::fs::read_to_string(dir.join(".local/share/unbox/meta/pinned.toml")).unwrap();
+    assert!(meta.contains("shell = \"/bin/sh\""), "{meta}");
+    assert!(meta.contains("home = "), "{meta}");
+    std::env::set_var("SHELL", "/bin/bash");
+    assert_eq!(Config::read("pinned").unwrap().shell, "/bin/sh");
+    std::fs::remove_dir_all(dir).unwrap();
+}
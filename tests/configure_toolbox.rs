@@ -0,0 +1,52 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+mod common;
+
+use common::Storage;
+
+#[test]
+fn show_is_read_only() {
+    let storage = Storage::new("configure-show");
+    let output = storage
+        .unbox()
+        .args(["configure", "typo", "--show"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(!storage.meta("typo").exists());
+
+    storage.toolbox("shown", &["--hostname", "shown-host"]);
+    let output = storage
+        .unbox()
+        .args(["configure", "shown", "--show"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("hostname = \"shown-host\""), "{stdout}");
+}
+
+#[test]
+fn shell_without_environment() {
+    let storage = Storage::new("configure-shell");
+    storage.toolbox("configured", &["--shell", "/bin/zsh"]);
+
+    let output = storage
+        .unbox()
+        .args(["configure", "configured", "--show"])
+        .env_remove("SHELL")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("shell = \"/bin/zsh\""), "{stdout}");
+    let status = storage
+        .unbox()
+        .args(["list", "--format", "json"])
+        .env_remove("SHELL")
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
@@ -2,6 +2,7 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use unbox::config::Engine;
 use unbox::create::*;
 use unbox::remove::*;
 
@@ -11,14 +12,21 @@ fn create_alpine() {
         name: "alpine-test".into(),
         tar: None,
         image: Some("docker.io/alpine:edge".into()),
+        oci_layout: None,
+        docker_archive: None,
         engine: Some(Engine::Podman),
         shell: None,
+        path: None,
+        sudo: false,
         quiet: true,
     };
     create(args).unwrap();
 
     let args = Remove {
         names: vec!["alpine-test".into()],
+        force: false,
+        yes: true,
+        dry_run: false,
     };
     remove(args).unwrap()
 }
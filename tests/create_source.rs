@@ -0,0 +1,63 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+mod common;
+
+use common::Storage;
+
+#[test]
+fn exactly_one_source() {
+    let storage = Storage::new("create-source");
+    for args in [&[][..], &["-t", "alpine.tar", "-i", "alpine"][..]] {
+        let output = storage
+            .unbox()
+            .args(["create", "sourced"])
+            .args(args)
+            .output()
+            .unwrap();
+        // Usage errors are reported by clap before anything is created
+        assert_eq!(output.status.code(), Some(2));
+        assert!(!storage.meta("sourced").exists());
+        assert!(!storage.image("sourced").exists());
+    }
+}
+
+#[test]
+fn failed_create_leaves_nothing() {
+    let storage = Storage::new("create-failed");
+    for _ in 0..2 {
+        let output = storage
+            .unbox()
+            .args(["create", "broken", "-q", "-t", "/does/not/exist.tar"])
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(!stderr.contains("already"), "{stderr}");
+        assert!(!storage.meta("broken").exists());
+        assert!(!storage.image("broken").exists());
+    }
+
+    let archive = storage.dir.join("minimal.tar");
+    let mut builder = tar::Builder::new(Vec::new());
+    let passwd = b"root:x:0:0:root:/root:/bin/sh\n";
+    let mut header = tar::Header::new_gnu();
+    header.set_size(passwd.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder
+        .append_data(&mut header, "etc/passwd", &passwd[..])
+        .unwrap();
+    std::fs::write(&archive, builder.into_inner().unwrap()).unwrap();
+    let status = storage
+        .unbox()
+        .args(["create", "minimal", "-q", "-s", "/bin/sh", "-t"])
+        .arg(&archive)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let meta = std::fs::read_to_string(storage.meta("minimal")).unwrap();
+    assert!(meta.contains("shell = \"/bin/sh\""), "{meta}");
+    assert!(storage.image("minimal").join("etc/passwd").exists());
+}
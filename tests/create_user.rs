@@ -0,0 +1,54 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use unbox::user::*;
+
+#[test]
+fn create_user() {
+    let root = std::env::temp_dir().join(format!("unbox-user-test-{}", std::process::id()));
+    let etc = root.join("etc");
+    std::fs::create_dir_all(&etc).unwrap();
+    std::fs::write(
+        etc.join("passwd"),
+        "root:x:0:0:root:/root:/bin/bash\nubuntu:x:1000:1000::/home/ubuntu:/bin/bash\n",
+    )
+    .unwrap();
+    std::fs::write(
+        etc.join("group"),
+        "root:x:0:\nadm:x:4:syslog,ubuntu\nwheel:x:10:root\nubuntu:x:1000:\n",
+    )
+    .unwrap();
+    std::fs::write(
+        etc.join("shadow"),
+        "root:*:19000:0:99999:7:::\nubuntu:!:19000:0:99999:7:::\n",
+    )
+    .unwrap();
+
+    let user = User {
+        name: "dev".into(),
+        uid: 1000,
+        group: "dev".into(),
+        gid: 1000,
+        home: "/home/dev".into(),
+        shell: "/bin/zsh".into(),
+    };
+    user.create(&root, true).unwrap();
+
+    let read = |file: &str| std::fs::read_to_string(etc.join(file)).unwrap();
+    assert_eq!(
+        read("passwd"),
+        "root:x:0:0:root:/root:/bin/bash\ndev:x:1000:1000:dev:/home/dev:/bin/zsh\n"
+    );
+    assert_eq!(
+        read("group"),
+        "root:x:0:\nadm:x:4:syslog\nwheel:x:10:root,dev\ndev:x:1000:\n"
+    );
+    // The account that had the uid of the user is replaced everywhere
+    assert_eq!(
+        read("shadow"),
+        "root:*:19000:0:99999:7:::\ndev:!::0:99999:7:::\n"
+    );
+    assert!(root.join("home/dev").is_dir());
+    std::fs::remove_dir_all(root).unwrap();
+}
@@ -0,0 +1,53 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::io::{Read, Write};
+
+use unbox::compression::*;
+
+const CONTENT: &[u8] = b"root filesystem contents";
+
+#[test]
+fn detect_formats() {
+    assert_eq!(Compression::detect(&gzip()), Compression::Gzip);
+    assert_eq!(Compression::detect(&xz()), Compression::Xz);
+    assert_eq!(Compression::detect(&zstd()), Compression::Zstd);
+    assert_eq!(Compression::detect(&bzip2()), Compression::Bzip2);
+    assert_eq!(Compression::detect(CONTENT), Compression::None);
+    assert_eq!(Compression::detect(&[]), Compression::None);
+}
+
+#[test]
+fn decompress_formats() {
+    for compressed in [gzip(), xz(), zstd(), bzip2(), CONTENT.to_vec()] {
+        let mut decompressed = Vec::new();
+        decompress(compressed.as_slice())
+            .unwrap()
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, CONTENT);
+    }
+}
+
+fn gzip() -> Vec<u8> {
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+    encoder.write_all(CONTENT).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn xz() -> Vec<u8> {
+    let mut encoder = xz2::write::XzEncoder::new(Vec::new(), 1);
+    encoder.write_all(CONTENT).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn zstd() -> Vec<u8> {
+    zstd::encode_all(CONTENT, 1).unwrap()
+}
+
+fn bzip2() -> Vec<u8> {
+    let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::fast());
+    encoder.write_all(CONTENT).unwrap();
+    encoder.finish().unwrap()
+}
@@ -0,0 +1,40 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+mod common;
+
+use common::Storage;
+use unbox::namespaces::Mapper;
+
+fn uid_map(storage: &Storage, args: &[&str]) -> String {
+    let output = storage
+        .unbox()
+        .arg("run")
+        .args(args)
+        .args(["direct-tests", "--", "cat", "/proc/self/uid_map"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("newuidmap"), "{stderr}");
+    let uid_map = String::from_utf8(output.stdout).unwrap();
+    uid_map.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[test]
+fn map_only_the_user() {
+    if Mapper::detect() == Mapper::Helpers {
+        eprintln!("Skipping map_only_the_user, it needs newuidmap and newgidmap to be missing");
+        return;
+    }
+    let storage = Storage::new("direct");
+    storage.fixture("direct-tests");
+    let uid = users::get_current_uid();
+
+    assert_eq!(uid_map(&storage, &[]), format!("0 {uid} 1"));
+    assert_eq!(
+        uid_map(&storage, &["--userns", "keep-id"]),
+        format!("{uid} {uid} 1")
+    );
+}
@@ -0,0 +1,61 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::process::Command;
+
+fn unbox() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_unbox"))
+}
+
+fn variables(command: &mut Command) -> Vec<String> {
+    let output = command
+        .env("LD_LIBRARY_PATH", "/host/lib")
+        .env("UNBOX_TEST_HOST", "host")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(String::from)
+        .collect()
+}
+
+#[test]
+fn environment_policy() {
+    let env = variables(unbox().args(["run", "exec-tests", "--", "env"]));
+    assert!(env.contains(&"UNBOX_TEST_HOST=host".to_string()));
+    assert!(!env.iter().any(|v| v.starts_with("LD_LIBRARY_PATH=")));
+
+    let file = std::env::temp_dir().join(format!("unbox-env-test-{}", std::process::id()));
+    std::fs::write(&file, "# comment\nFROM_FILE=file\nOVERRIDDEN=file\n").unwrap();
+    let env = variables(
+        unbox()
+            .args(["run", "exec-tests", "--clean-env", "--env-file"])
+            .arg(&file)
+            .args(["-e", "OVERRIDDEN=cli", "-e", "UNBOX_TEST_HOST", "--", "env"]),
+    );
+    std::fs::remove_file(file).unwrap();
+    assert!(env.contains(&"FROM_FILE=file".to_string()));
+    assert!(env.contains(&"OVERRIDDEN=cli".to_string()));
+    assert!(env.contains(&"UNBOX_TEST_HOST=host".to_string()));
+    assert!(!env.iter().any(|v| v.starts_with("SHELL=")));
+
+    let status = unbox()
+        .args(["configure", "exec-tests", "-e", "UNBOX_TEST_CONFIGURED=yes"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let env = variables(unbox().args(["run", "exec-tests", "--", "env"]));
+    let status = unbox()
+        .args([
+            "configure",
+            "exec-tests",
+            "--unset-env",
+            "UNBOX_TEST_CONFIGURED",
+        ])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(env.contains(&"UNBOX_TEST_CONFIGURED=yes".to_string()));
+}
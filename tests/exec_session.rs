@@ -0,0 +1,87 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+/// Tests that start sessions of the shared toolbox would join the sessions of each other
+static SESSIONS: Mutex<()> = Mutex::new(());
+
+fn unbox() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_unbox"))
+}
+
+#[test]
+fn join_running_session() {
+    let _sessions = SESSIONS.lock().unwrap_or_else(|e| e.into_inner());
+    let mut session = unbox()
+        .args(["run", "exec-tests", "--", "sh", "-c"])
+        .arg("readlink /proc/self/ns/mnt; sleep 3")
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut namespace = String::new();
+    BufReader::new(session.stdout.take().unwrap())
+        .read_line(&mut namespace)
+        .unwrap();
+
+    let joined = unbox()
+        .args(["exec", "exec-tests", "--", "readlink", "/proc/self/ns/mnt"])
+        .output()
+        .unwrap();
+    assert!(joined.status.success());
+    assert_eq!(String::from_utf8_lossy(&joined.stdout), namespace);
+
+    let status = unbox()
+        .args(["exec", "exec-tests", "--", "sh", "-c", "exit 4"])
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(4));
+    session.kill().unwrap();
+    session.wait().unwrap();
+
+    let status = unbox().args(["exec", "exec-tests", "--", "true"]).status();
+    assert_eq!(status.unwrap().code(), Some(125));
+}
+
+/// Namespace that `enter` puts the user in, with the commands read from stdin by the shell
+fn entered(args: &[&str], namespace: &str) -> String {
+    use std::io::Write;
+
+    let mut enter = unbox()
+        .args(["enter", "exec-tests"])
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = enter.stdin.take().unwrap();
+    writeln!(stdin, "readlink /proc/self/ns/{namespace}").unwrap();
+    drop(stdin);
+    let output = enter.wait_with_output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn enter_session_with_same_namespaces() {
+    let _sessions = SESSIONS.lock().unwrap_or_else(|e| e.into_inner());
+    let mut session = unbox()
+        .args(["run", "--network", "none", "exec-tests", "--", "sh", "-c"])
+        .arg("readlink /proc/self/ns/net; sleep 3")
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut namespace = String::new();
+    BufReader::new(session.stdout.take().unwrap())
+        .read_line(&mut namespace)
+        .unwrap();
+
+    // The network of the host is asked for, so the isolated session cannot be joined
+    assert_ne!(entered(&[], "net"), namespace);
+    assert_eq!(entered(&["--network", "none"], "net"), namespace);
+    session.kill().unwrap();
+    session.wait().unwrap();
+}
@@ -0,0 +1,71 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+mod common;
+
+use common::{add_tool, check_tool, Storage};
+
+#[test]
+fn export_and_import() {
+    let storage = Storage::new("export");
+    let image = storage.toolbox("original", &["--hostname", "exported", "-e", "EDITOR=vi"]);
+    add_tool(&image);
+
+    let archive = storage.dir.join("original.tar.zst");
+    let status = storage
+        .unbox()
+        .args(["export", "original", "-o"])
+        .arg(&archive)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let magic = std::fs::read(&archive).unwrap();
+    assert!(magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]));
+
+    let status = storage
+        .unbox()
+        .arg("import")
+        .arg(&archive)
+        .arg("copy")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let copy = storage.image("copy");
+    check_tool(&copy);
+    assert!(!copy.join(".unbox-meta.toml").exists());
+    let meta = std::fs::read_to_string(storage.meta("copy")).unwrap();
+    assert!(meta.contains("hostname = \"exported\""), "{meta}");
+    assert!(meta.contains("EDITOR = \"vi\""), "{meta}");
+    assert!(
+        meta.contains(&copy.to_string_lossy().into_owned()),
+        "{meta}"
+    );
+
+    // The name is taken from the file, which is already used
+    let output = storage
+        .unbox()
+        .arg("import")
+        .arg(&archive)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
+
+#[test]
+fn import_broken_archive() {
+    let storage = Storage::new("import-broken");
+    let archive = storage.dir.join("broken.tar");
+    std::fs::create_dir_all(&storage.dir).unwrap();
+    std::fs::write(&archive, vec![b'x'; 1024]).unwrap();
+
+    let output = storage
+        .unbox()
+        .arg("import")
+        .arg(&archive)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(!storage.image("broken").exists());
+    assert!(!storage.meta("broken").exists());
+}
@@ -0,0 +1,38 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::process::Command;
+
+fn unbox() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_unbox"))
+}
+
+#[test]
+fn inspect_toolbox() {
+    let output = unbox()
+        .args(["inspect", "exec-tests", "--format", "json"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["name"], "exec-tests");
+    assert_eq!(json["config"]["hostname"], "exec-tests");
+    assert_eq!(json["os_release"]["ID"], "fixture");
+    assert!(json["usage"]["apparent"].as_u64().unwrap() > 0);
+    let proc = json["mounts"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|m| m["target"] == "/proc")
+        .unwrap();
+    assert_eq!(proc["source"], "/host/proc");
+    assert_eq!(proc["resolved"], "/proc");
+
+    let output = unbox().args(["inspect", "exec-tests"]).output().unwrap();
+    let text = String::from_utf8_lossy(&output.stdout);
+    assert!(text.contains("system:    Fixture Linux"));
+
+    let status = unbox().args(["inspect", "not-a-toolbox"]).status().unwrap();
+    assert!(!status.success());
+}
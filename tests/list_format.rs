@@ -0,0 +1,37 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::process::Command;
+
+#[test]
+fn list_formats() {
+    let dir = std::env::temp_dir().join(format!("unbox-list-test-{}", std::process::id()));
+    let unbox = || {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_unbox"));
+        command
+            .env("UNBOX_STORAGE", &dir)
+            .env("XDG_CONFIG_HOME", dir.join("config"));
+        command
+    };
+    let status = unbox()
+        .args(["configure", "listed", "--hostname", "box"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let output = unbox().args(["list", "--format", "json"]).output().unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json[0]["name"], "listed");
+    assert_eq!(json[0]["hostname"], "box");
+    assert_eq!(json[0]["image_exists"], false);
+    assert_eq!(json[0]["mounts"]["/proc"], "/host/proc");
+
+    let output = unbox().args(["list", "--format", "toml"]).output().unwrap();
+    let toml: toml::Value = toml::from_str(&String::from_utf8_lossy(&output.stdout)).unwrap();
+    assert_eq!(toml["toolbox"][0]["hostname"].as_str(), Some("box"));
+
+    let output = unbox().args(["list", "--quiet"]).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "listed\n");
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,46 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::process::Command;
+
+fn unbox() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_unbox"))
+}
+
+#[test]
+fn read_only_mount() {
+    let dir = std::env::temp_dir().join(format!("unbox-mount-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("file"), "content").unwrap();
+    let mount = format!("{}:/mnt/mount-test:ro,nosuid", dir.display());
+
+    let configured = unbox()
+        .args(["configure", "exec-tests", "--mount", &mount, "--show"])
+        .output()
+        .unwrap();
+    assert!(configured.status.success());
+    let shown = String::from_utf8_lossy(&configured.stdout);
+    assert!(shown.contains("\"/mnt/mount-test\" = { options = [\"ro\", \"nosuid\"]"));
+
+    let output = unbox()
+        .args(["run", "exec-tests", "--", "sh", "-c"])
+        .arg("cat /mnt/mount-test/file && touch /mnt/mount-test/new")
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "content");
+    assert!(!output.status.success());
+    assert!(!dir.join("new").exists());
+
+    let status = unbox()
+        .args(["configure", "exec-tests", "--unmount", "/mnt/mount-test"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let output = unbox()
+        .args(["run", "exec-tests", "--", "cat", "/mnt/mount-test/file"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    std::fs::remove_dir_all(dir).unwrap();
+}
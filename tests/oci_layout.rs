@@ -0,0 +1,148 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+use unbox::oci::*;
+
+#[test]
+fn unpack_oci_layout() {
+    let dir = temporary("layout");
+    let lower = gzip(&layer(&[("etc/hostname", "lower"), ("etc/removed", "")]));
+    let upper = layer(&[("etc/.wh.removed", ""), ("etc/motd", "upper")]);
+    let manifest = format!(
+        r#"{{"schemaVersion":2,"layers":[
+            {{"mediaType":"application/vnd.oci.image.layer.v1.tar+gzip","digest":"{}","size":{}}},
+            {{"mediaType":"application/vnd.oci.image.layer.v1.tar","digest":"{}","size":{}}}
+        ]}}"#,
+        write_blob(&dir, &lower),
+        lower.len(),
+        write_blob(&dir, &upper),
+        upper.len()
+    );
+    let index = format!(
+        r#"{{"schemaVersion":2,"manifests":[
+            {{"mediaType":"application/vnd.oci.image.manifest.v1+json","digest":"{}","size":{}}}
+        ]}}"#,
+        write_blob(&dir, manifest.as_bytes()),
+        manifest.len()
+    );
+    std::fs::write(dir.join("index.json"), index).unwrap();
+
+    let root = dir.join("root");
+    std::fs::create_dir(&root).unwrap();
+    from_layout(&dir).unwrap().unpack(&root).unwrap();
+
+    assert_eq!(read(&root, "etc/hostname").as_deref(), Some("lower"));
+    assert_eq!(read(&root, "etc/motd").as_deref(), Some("upper"));
+    assert!(!root.join("etc/removed").exists());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn reject_corrupted_layout() {
+    let dir = temporary("corrupted");
+    let blob = layer(&[("etc/hostname", "lower")]);
+    let digest = write_blob(&dir, &blob);
+    std::fs::write(dir.join("blobs/sha256").join(&digest[7..]), b"corrupted").unwrap();
+    let manifest = format!(
+        r#"{{"layers":[{{"mediaType":"application/vnd.oci.image.layer.v1.tar","digest":"{digest}","size":1}}]}}"#
+    );
+    let index = format!(
+        r#"{{"manifests":[{{"mediaType":"application/vnd.oci.image.manifest.v1+json","digest":"{}","size":1}}]}}"#,
+        write_blob(&dir, manifest.as_bytes())
+    );
+    std::fs::write(dir.join("index.json"), index).unwrap();
+
+    assert!(from_layout(&dir).is_err());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn unpack_docker_archive() {
+    let dir = temporary("archive");
+    let manifest = r#"[{"Config":"config.json","RepoTags":["test:latest"],
+        "Layers":["lower/layer.tar","upper/layer.tar"]}]"#;
+    let mut builder = tar::Builder::new(Vec::new());
+    append(&mut builder, "manifest.json", manifest.as_bytes());
+    append(&mut builder, "config.json", b"{}");
+    let lower = layer(&[("opt/app/old", "lower"), ("opt/app/kept", "lower")]);
+    append(&mut builder, "lower/layer.tar", &lower);
+    let upper = layer(&[("opt/app/.wh..wh..opq", ""), ("opt/app/new", "upper")]);
+    append(&mut builder, "upper/layer.tar", &upper);
+    let archive = dir.join("image.tar");
+    std::fs::write(&archive, builder.into_inner().unwrap()).unwrap();
+
+    let root = dir.join("root");
+    std::fs::create_dir(&root).unwrap();
+    std::fs::create_dir(dir.join("extracted")).unwrap();
+    from_docker_archive(&archive, &dir.join("extracted"))
+        .unwrap()
+        .unpack(&root)
+        .unwrap();
+
+    assert_eq!(read(&root, "opt/app/new").as_deref(), Some("upper"));
+    assert!(!root.join("opt/app/old").exists());
+    assert!(!root.join("opt/app/kept").exists());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn reject_escaping_whiteouts() {
+    let dir = temporary("whiteout");
+    let root = dir.join("root");
+    std::fs::create_dir_all(root.join("etc")).unwrap();
+    std::fs::write(root.join("etc/kept"), "").unwrap();
+    std::fs::write(dir.join("sibling"), "").unwrap();
+
+    for whiteout in [".wh...", ".wh..", "etc/.wh...", "etc/.wh..", "etc/.wh."] {
+        let layer = layer(&[(whiteout, "")]);
+        assert!(apply_layer(layer.as_slice(), &root).is_err(), "{whiteout}");
+        assert!(root.join("etc/kept").exists(), "{whiteout}");
+        assert!(dir.join("sibling").exists(), "{whiteout}");
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+fn temporary(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("unbox-{name}-test-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("blobs/sha256")).unwrap();
+    dir
+}
+
+fn write_blob(dir: &Path, data: &[u8]) -> String {
+    let hex = format!("{:x}", Sha256::digest(data));
+    std::fs::write(dir.join("blobs/sha256").join(&hex), data).unwrap();
+    format!("sha256:{hex}")
+}
+
+fn read(root: &Path, path: &str) -> Option<String> {
+    std::fs::read_to_string(root.join(path)).ok()
+}
+
+fn layer(files: &[(&str, &str)]) -> Vec<u8> {
+    let mut builder = tar::Builder::new(Vec::new());
+    for (path, content) in files {
+        append(&mut builder, path, content.as_bytes());
+    }
+    builder.into_inner().unwrap()
+}
+
+fn append(builder: &mut tar::Builder<Vec<u8>>, path: &str, content: &[u8]) {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(content.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder
+        .append_data(&mut header, Path::new(path), content)
+        .unwrap();
+}
+
+fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
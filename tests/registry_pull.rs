@@ -0,0 +1,194 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+mod common;
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::Path;
+
+use common::Storage;
+use sha2::{Digest, Sha256};
+use unbox::oci::current_architecture;
+use unbox::registry::*;
+
+const TOKEN: &str = "stand-in-token";
+
+#[test]
+fn parse_reference() {
+    let reference: Reference = "docker.io/archlinux".parse().unwrap();
+    assert_eq!(reference.registry, "docker.io");
+    assert_eq!(reference.repository, "library/archlinux");
+    assert_eq!(reference.reference, "latest");
+
+    let reference: Reference = "alpine:edge".parse().unwrap();
+    assert_eq!(reference.repository, "library/alpine");
+    assert_eq!(reference.reference, "edge");
+
+    let reference: Reference = "localhost:5000/tools/dev@sha256:abcd".parse().unwrap();
+    assert_eq!(reference.registry, "localhost:5000");
+    assert_eq!(reference.repository, "tools/dev");
+    assert_eq!(reference.reference, "sha256:abcd");
+}
+
+#[test]
+fn pull_from_registry() {
+    let lower = layer(&[
+        ("etc/os-release", "ID=test\n"),
+        ("etc/removed", "lower"),
+        ("opt/dir/old", "lower"),
+    ]);
+    let upper = layer(&[
+        ("etc/.wh.removed", ""),
+        ("opt/dir/.wh..wh..opq", ""),
+        ("opt/dir/new", "upper"),
+    ]);
+    let lower = gzip(&lower);
+    let manifest = format!(
+        r#"{{"schemaVersion":2,"mediaType":"application/vnd.oci.image.manifest.v1+json",
+            "config":{{"mediaType":"application/vnd.oci.image.config.v1+json","digest":"{}","size":2}},
+            "layers":[
+                {{"mediaType":"application/vnd.oci.image.layer.v1.tar+gzip","digest":"{}","size":{}}},
+                {{"mediaType":"application/vnd.oci.image.layer.v1.tar","digest":"{}","size":{}}}
+            ]}}"#,
+        digest(b"{}"),
+        digest(&lower),
+        lower.len(),
+        digest(&upper),
+        upper.len()
+    );
+    let index = format!(
+        r#"{{"schemaVersion":2,"manifests":[
+            {{"mediaType":"application/vnd.oci.image.manifest.v1+json","digest":"sha256:{}","size":1,
+              "platform":{{"architecture":"not-this-one","os":"linux"}}}},
+            {{"mediaType":"application/vnd.oci.image.manifest.v1+json","digest":"{}","size":{},
+              "platform":{{"architecture":"{}","os":"linux"}}}}
+        ]}}"#,
+        "0".repeat(64),
+        digest(manifest.as_bytes()),
+        manifest.len(),
+        current_architecture()
+    );
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let mut routes = HashMap::new();
+    let index_digest = digest(index.as_bytes());
+    routes.insert(
+        "/v2/test/image/manifests/latest".to_string(),
+        index.into_bytes(),
+    );
+    routes.insert(
+        format!("/v2/test/image/manifests/{}", digest(manifest.as_bytes())),
+        manifest.into_bytes(),
+    );
+    routes.insert(format!("/v2/test/image/blobs/{}", digest(&lower)), lower);
+    routes.insert(format!("/v2/test/image/blobs/{}", digest(&upper)), upper);
+    std::thread::spawn(move || serve(listener, port, routes));
+
+    let dir = std::env::temp_dir().join(format!("unbox-registry-test-{}", std::process::id()));
+    let root = dir.join("root");
+    std::fs::create_dir_all(&root).unwrap();
+    let reference: Reference = format!("127.0.0.1:{port}/test/image").parse().unwrap();
+    let image = pull(&reference, &dir.join("layers")).unwrap();
+    image.unpack(&root).unwrap();
+    assert_eq!(image.digest, Some(index_digest));
+
+    let read = |path: &str| std::fs::read_to_string(root.join(path)).ok();
+    assert_eq!(read("etc/os-release").as_deref(), Some("ID=test\n"));
+    assert_eq!(read("opt/dir/new").as_deref(), Some("upper"));
+    assert!(!root.join("etc/removed").exists());
+    assert!(!root.join("opt/dir/old").exists());
+    assert!(!root.join("opt/dir/.wh..wh..opq").exists());
+    std::fs::remove_dir_all(dir).unwrap();
+
+    // The layers are downloaded to a private directory that is removed afterwards
+    let storage = Storage::new("registry-create");
+    let status = storage
+        .unbox()
+        .args(["create", "pulled", "-q", "-s", "/bin/sh", "-i"])
+        .arg(format!("127.0.0.1:{port}/test/image"))
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let pulled = storage.image("pulled");
+    assert_eq!(
+        std::fs::read_to_string(pulled.join("etc/os-release")).unwrap(),
+        "ID=test\n"
+    );
+    let leftovers = std::fs::read_dir(std::env::temp_dir())
+        .unwrap()
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .any(|name| name.starts_with("unbox-pulled-"));
+    assert!(!leftovers);
+}
+
+/// Minimal stand-in for a registry that requires an anonymous bearer token
+fn serve(listener: TcpListener, port: u16, routes: HashMap<String, Vec<u8>>) {
+    for stream in listener.incoming() {
+        let mut stream = stream.unwrap();
+        let mut reader = BufReader::new(&stream);
+        let mut request = String::new();
+        reader.read_line(&mut request).unwrap();
+        let path = request.split(' ').nth(1).unwrap().to_string();
+        let mut authorized = false;
+        loop {
+            let mut header = String::new();
+            reader.read_line(&mut header).unwrap();
+            if header.trim().is_empty() {
+                break;
+            }
+            authorized |= header.trim() == format!("Authorization: Bearer {TOKEN}");
+        }
+        let (status, headers, body) = if path.starts_with("/token") {
+            (
+                "200 OK",
+                String::new(),
+                format!(r#"{{"token":"{TOKEN}"}}"#).into_bytes(),
+            )
+        } else if !authorized {
+            let challenge = format!(
+                "WWW-Authenticate: Bearer realm=\"http://127.0.0.1:{port}/token\",service=\"stand-in\"\r\n"
+            );
+            ("401 Unauthorized", challenge, Vec::new())
+        } else if let Some(body) = routes.get(&path) {
+            ("200 OK", String::new(), body.clone())
+        } else {
+            ("404 Not Found", String::new(), Vec::new())
+        };
+        write!(
+            stream,
+            "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n{headers}\r\n",
+            body.len()
+        )
+        .unwrap();
+        stream.write_all(&body).unwrap();
+    }
+}
+
+fn layer(files: &[(&str, &str)]) -> Vec<u8> {
+    let mut builder = tar::Builder::new(Vec::new());
+    for (path, content) in files {
+        let content = content.as_bytes();
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, Path::new(path), content)
+            .unwrap();
+    }
+    builder.into_inner().unwrap()
+}
+
+fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn digest(data: &[u8]) -> String {
+    format!("sha256:{:x}", Sha256::digest(data))
+}
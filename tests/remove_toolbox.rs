@@ -0,0 +1,137 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+mod common;
+
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+use common::Storage;
+use unbox::namespaces::Mapper;
+
+#[test]
+fn remove_read_only_tree() {
+    let storage = Storage::new("remove");
+    let image = storage.toolbox("removed", &[]);
+    std::fs::create_dir_all(image.join("usr/share/locked")).unwrap();
+    std::fs::write(image.join("usr/share/locked/file"), "").unwrap();
+    for locked in ["usr/share/locked", "usr/share"] {
+        let read_only = std::fs::Permissions::from_mode(0o555);
+        std::fs::set_permissions(image.join(locked), read_only).unwrap();
+    }
+
+    let status = storage
+        .unbox()
+        .args(["rm", "-y", "removed"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(!image.exists());
+    assert!(!storage.meta("removed").exists());
+}
+
+#[test]
+fn remove_subordinate_files() {
+    // Root removes every file without a namespace, and without the helpers no id can be mapped
+    if users::get_current_uid() == 0 {
+        eprintln!("Skipping remove_subordinate_files, it needs to run as a normal user");
+        return;
+    }
+    if Mapper::detect() == Mapper::Direct {
+        eprintln!("Skipping remove_subordinate_files, it needs newuidmap and newgidmap");
+        return;
+    }
+    let storage = Storage::new("remove-subordinate");
+    let image = storage.fixture("subordinate");
+    let script =
+        "mkdir -p /locked && touch /locked/file && chown -R 1234:1234 /locked && chmod 555 /locked";
+    let status = storage
+        .unbox()
+        .args(["run", "subordinate", "--", "sh", "-c", script])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let locked = std::fs::metadata(image.join("locked/file")).unwrap();
+    assert_ne!(locked.uid(), users::get_current_uid());
+
+    let status = storage
+        .unbox()
+        .args(["rm", "-y", "subordinate"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(!image.exists());
+}
+
+#[test]
+fn remove_missing_name() {
+    let storage = Storage::new("remove-missing");
+    let image = storage.toolbox("kept", &[]);
+
+    let output = storage
+        .unbox()
+        .args(["rm", "-y", "kept", "missing"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("There is no toolbox named missing"),
+        "{stderr}"
+    );
+    assert!(image.exists());
+    assert!(!storage.meta("missing").exists());
+
+    let output = storage
+        .unbox()
+        .args(["rm", "-y", "--force", "missing", "kept"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stdout.contains("Removed kept"), "{stdout}");
+    assert!(stderr.contains("Could not remove missing"), "{stderr}");
+    assert!(!image.exists());
+}
+
+#[test]
+fn remove_dry_run() {
+    let storage = Storage::new("remove-dry-run");
+    let image = storage.toolbox("listed", &[]);
+
+    let output = storage
+        .unbox()
+        .args(["rm", "--dry-run", "listed"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("listed"), "{stdout}");
+    assert!(
+        stdout.contains(&image.to_string_lossy().into_owned()),
+        "{stdout}"
+    );
+    assert!(image.join("etc/file").exists());
+    assert!(storage.meta("listed").exists());
+}
+
+#[test]
+fn remove_not_confirmed() {
+    use std::process::Stdio;
+
+    let storage = Storage::new("remove-confirm");
+    let image = storage.toolbox("asked", &[]);
+
+    let output = storage
+        .unbox()
+        .args(["rm", "asked"])
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("use -y"), "{stderr}");
+    assert!(image.exists());
+    assert!(storage.meta("asked").exists());
+}
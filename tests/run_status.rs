@@ -0,0 +1,90 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Command, ExitStatus};
+
+fn run(name: &str, cmd: &[&str]) -> ExitStatus {
+    Command::new(env!("CARGO_BIN_EXE_unbox"))
+        .args(["run", name, "--"])
+        .args(cmd)
+        .status()
+        .unwrap()
+}
+
+#[test]
+fn propagate_exit_code() {
+    assert_eq!(run("exec-tests", &["true"]).code(), Some(0));
+    assert_eq!(run("exec-tests", &["false"]).code(), Some(1));
+    assert_eq!(run("exec-tests", &["sh", "-c", "exit 42"]).code(), Some(42));
+}
+
+#[test]
+fn propagate_signal() {
+    let status = run("exec-tests", &["sh", "-c", "kill -TERM $$"]);
+    assert_eq!(status.signal(), Some(15));
+}
+
+#[test]
+fn command_not_found() {
+    assert_eq!(run("exec-tests", &["does-not-exist"]).code(), Some(127));
+    assert_eq!(run("exec-tests", &["/does/not/exist"]).code(), Some(127));
+}
+
+#[test]
+fn command_not_executable() {
+    assert_eq!(run("exec-tests", &["/etc/passwd"]).code(), Some(126));
+}
+
+#[test]
+fn unbox_failure() {
+    let status = run("exec-tests-does-not-exist", &["true"]);
+    assert_eq!(status.code(), Some(125));
+}
+
+#[test]
+fn storage_failure() {
+    let status = Command::new(env!("CARGO_BIN_EXE_unbox"))
+        .args(["run", "exec-tests", "--", "true"])
+        .env("UNBOX_STORAGE", "/proc/unbox-storage")
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(125));
+}
+
+#[test]
+fn pid_namespace() {
+    let pidns = |cmd: &str| {
+        Command::new(env!("CARGO_BIN_EXE_unbox"))
+            .args(["run", "--pidns", "exec-tests", "--", "sh", "-c", cmd])
+            .status()
+            .unwrap()
+    };
+    assert_eq!(pidns("test $PPID = 1").code(), Some(0));
+    assert_eq!(
+        pidns("test -d /proc/2 && ! test -d /proc/3").code(),
+        Some(0)
+    );
+    assert_eq!(pidns("exit 42").code(), Some(42));
+    // Killed the same way as without a PID namespace, which is not an exit with `128 + signal`
+    assert_eq!(pidns("kill -TERM $$").signal(), Some(15));
+    assert_eq!(pidns("exit 143").code(), Some(143));
+}
+
+#[test]
+fn network_isolation() {
+    let network = |mode: &str| {
+        // Connections to a closed port are refused when the loopback interface is up
+        let probe =
+            "case $( { echo > /dev/tcp/127.0.0.1/1; } 2>&1 ) in *refused*) ;; *) exit 2;; esac";
+        Command::new(env!("CARGO_BIN_EXE_unbox"))
+            .args(["run", "--network", mode, "exec-tests", "--"])
+            .args(["bash", "-c", probe])
+            .status()
+            .unwrap()
+    };
+    assert_eq!(network("host").code(), Some(0));
+    assert_eq!(network("loopback").code(), Some(0));
+    assert_eq!(network("none").code(), Some(2));
+}
@@ -2,12 +2,17 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use unbox::environment::Variables;
 use unbox::run::*;
 
 #[test]
 fn run_true() {
     let args = Execute::Run(Run {
         name: "exec-tests".into(),
+        userns: None,
+        pidns: false,
+        network: None,
+        variables: Variables::default(),
         cmd: "true".into(),
         args: vec![],
     });
@@ -18,6 +23,10 @@ fn run_true() {
 fn run_bin_true() {
     let args = Execute::Run(Run {
         name: "exec-tests".into(),
+        userns: None,
+        pidns: false,
+        network: None,
+        variables: Variables::default(),
         cmd: "/bin/true".into(),
         args: vec![],
     });
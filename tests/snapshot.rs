@@ -0,0 +1,119 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+mod common;
+
+use common::Storage;
+
+#[test]
+fn snapshot_and_rollback() {
+    let storage = Storage::new("snapshot");
+    let image = storage.toolbox("saved", &[]);
+    std::fs::write(image.join("etc/release"), "1").unwrap();
+
+    let status = storage
+        .unbox()
+        .args(["snapshot", "saved", "before-upgrade"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let output = storage
+        .unbox()
+        .args(["snapshot", "--list"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("before-upgrade"), "{stdout}");
+
+    // Package managers replace files instead of writing to them
+    std::fs::remove_file(image.join("etc/release")).unwrap();
+    std::fs::write(image.join("etc/release"), "2").unwrap();
+    std::fs::write(image.join("etc/added"), "").unwrap();
+    let status = storage
+        .unbox()
+        .args(["rollback", "saved", "before-upgrade"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let release = std::fs::read_to_string(image.join("etc/release")).unwrap();
+    assert_eq!(release, "1");
+    assert!(!image.join("etc/added").exists());
+    assert!(!storage.image(".saved-rollback").exists());
+
+    // The snapshot is kept to roll back again
+    std::fs::write(image.join("etc/added"), "").unwrap();
+    let status = storage
+        .unbox()
+        .args(["rollback", "saved", "before-upgrade"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(!image.join("etc/added").exists());
+
+    // The restored image does not share its files with the snapshot
+    std::fs::write(image.join("etc/release"), "3").unwrap();
+    let saved = storage
+        .dir
+        .join("snapshots/saved/before-upgrade/rootfs/etc/release");
+    assert_eq!(std::fs::read_to_string(saved).unwrap(), "1");
+    let status = storage
+        .unbox()
+        .args(["rollback", "saved", "before-upgrade"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let release = std::fs::read_to_string(image.join("etc/release")).unwrap();
+    assert_eq!(release, "1");
+
+    let output = storage
+        .unbox()
+        .args(["rollback", "saved", "missing"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+
+    // A copy left by an interrupted rollback is not a toolbox
+    std::fs::create_dir_all(storage.image(".saved-rollback")).unwrap();
+    let output = storage.unbox().args(["list", "-q"]).output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout, "saved\n");
+
+    let output = storage
+        .unbox()
+        .args(["rm", "--dry-run", "saved"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("before-upgrade"), "{stdout}");
+    let status = storage
+        .unbox()
+        .args(["rm", "-y", "saved"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(!storage.dir.join("snapshots/saved").exists());
+}
+
+#[test]
+fn snapshot_named_list() {
+    let storage = Storage::new("snapshot-list");
+    storage.toolbox("list", &[]);
+
+    let status = storage
+        .unbox()
+        .args(["snapshot", "list", "first"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(storage.dir.join("snapshots/list/first").is_dir());
+    let output = storage
+        .unbox()
+        .args(["snapshot", "--list", "list"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("first"), "{stdout}");
+    let output = storage.unbox().args(["snapshot"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+}
@@ -0,0 +1,50 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+mod common;
+
+use std::os::unix::process::ExitStatusExt;
+use std::time::Duration;
+
+use common::Storage;
+
+fn ps(storage: &Storage) -> String {
+    let output = storage.unbox().arg("ps").output().unwrap();
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn stop_running_session() {
+    // A throwaway copy, so that a bug in `rm` cannot break the toolbox of the other tests
+    let storage = Storage::new("stop");
+    let image = storage.fixture("stop-tests");
+    let mut session = storage
+        .unbox()
+        .args(["run", "stop-tests", "--", "sleep", "30"])
+        .spawn()
+        .unwrap();
+    let pid = session.id().to_string();
+    let listed = (0..50).any(|_| {
+        std::thread::sleep(Duration::from_millis(100));
+        ps(&storage).contains(&pid)
+    });
+    assert!(listed);
+
+    let status = storage
+        .unbox()
+        .args(["rm", "-y", "stop-tests"])
+        .status()
+        .unwrap();
+    assert!(!status.success());
+    assert!(image.exists());
+
+    let status = storage
+        .unbox()
+        .args(["stop", "stop-tests"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert_eq!(session.wait().unwrap().signal(), Some(15));
+    assert!(!ps(&storage).contains(&pid));
+}
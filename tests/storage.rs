@@ -0,0 +1,39 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::path::PathBuf;
+
+use unbox::config::*;
+
+#[test]
+fn resolve_storage() {
+    let dir = std::env::temp_dir().join(format!("unbox-storage-test-{}", std::process::id()));
+    std::env::set_var("HOME", &dir);
+    std::env::set_var("XDG_CONFIG_HOME", dir.join("config"));
+    std::env::remove_var("UNBOX_STORAGE");
+    std::env::remove_var("XDG_DATA_HOME");
+    assert_eq!(storage().unwrap(), dir.join(".local/share/unbox"));
+
+    std::env::set_var("XDG_DATA_HOME", dir.join("data"));
+    assert_eq!(storage().unwrap(), dir.join("data/unbox"));
+
+    // Toolboxes created before setting XDG_DATA_HOME are still found
+    std::fs::create_dir_all(dir.join(".local/share/unbox")).unwrap();
+    assert_eq!(storage().unwrap(), dir.join(".local/share/unbox"));
+    std::fs::create_dir_all(dir.join("data/unbox")).unwrap();
+    assert_eq!(storage().unwrap(), dir.join("data/unbox"));
+
+    let global = global_path().unwrap();
+    std::fs::create_dir_all(global.parent().unwrap()).unwrap();
+    std::fs::write(&global, "storage = \"~/volume/unbox\"\n").unwrap();
+    assert_eq!(storage().unwrap(), dir.join("volume/unbox"));
+
+    std::env::set_var("UNBOX_STORAGE", "/srv/unbox");
+    assert_eq!(storage().unwrap(), PathBuf::from("/srv/unbox"));
+    assert_eq!(
+        meta_path("tools").unwrap(),
+        PathBuf::from("/srv/unbox/meta/tools.toml")
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
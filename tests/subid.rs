@@ -0,0 +1,63 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use unbox::namespaces::*;
+use unbox::subid::*;
+
+const SUBUID: &str = "# comment
+alice:100000:65536
+bob:165536:65536
+1000:300000:1000
+invalid line
+alice:500000:0
+";
+
+#[test]
+fn parse_ranges() {
+    let ranges = parse(SUBUID, "alice", 1000);
+    assert_eq!(
+        ranges,
+        [
+            SubIds {
+                start: 100000,
+                count: 65536
+            },
+            SubIds {
+                start: 300000,
+                count: 1000
+            }
+        ]
+    );
+    assert!(parse(SUBUID, "carol", 1001).is_empty());
+}
+
+#[test]
+fn root_mappings() {
+    let mappings = UserNs::Root.mappings(1000, &parse(SUBUID, "alice", 1000));
+    assert_eq!(
+        lines(&mappings),
+        ["0 1000 1", "1 100000 65536", "65537 300000 1000"]
+    );
+}
+
+#[test]
+fn keep_id_mappings() {
+    let mappings = UserNs::KeepId.mappings(1000, &parse(SUBUID, "alice", 1000));
+    assert_eq!(
+        lines(&mappings),
+        [
+            "1000 1000 1",
+            "0 100000 1000",
+            "1001 101000 64536",
+            "65537 300000 1000"
+        ]
+    );
+}
+
+fn lines(mappings: &[Mapping]) -> Vec<String> {
+    mappings
+        .iter()
+        .map(|m| m.to_string().trim().to_string())
+        .collect()
+}
@@ -0,0 +1,27 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use unbox::usage::*;
+
+#[test]
+fn hardlinks_counted_once() {
+    let dir = std::env::temp_dir().join(format!("unbox-usage-test-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("sub")).unwrap();
+    std::fs::write(dir.join("file"), vec![0; 10_000]).unwrap();
+    let single = Usage::of(&dir);
+
+    std::fs::hard_link(dir.join("file"), dir.join("sub/link")).unwrap();
+    std::os::unix::fs::symlink("file", dir.join("symlink")).unwrap();
+    let linked = Usage::of(&dir);
+    assert!(linked.apparent < single.apparent + 10_000);
+    assert!(linked.real >= 10_000);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn human_sizes() {
+    assert_eq!(Size(512).to_string(), "512 B");
+    assert_eq!(Size(1536).to_string(), "1.5 KiB");
+    assert_eq!(Size(3 * 1024 * 1024 * 1024).to_string(), "3.0 GiB");
+}
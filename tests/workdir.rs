@@ -0,0 +1,33 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+mod common;
+
+use std::path::Path;
+
+use common::Storage;
+
+fn pwd(storage: &Storage, dir: &Path) -> String {
+    let output = storage
+        .unbox()
+        .args(["run", "workdir-tests", "--", "sh", "-c", "pwd"])
+        .current_dir(dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn translate_working_directory() {
+    let storage = Storage::new("workdir");
+    storage.fixture("workdir-tests");
+    let mounted = storage.dir.join("mounted");
+    std::fs::create_dir_all(&mounted).unwrap();
+
+    // The temporary directory is mounted at the same place
+    assert_eq!(pwd(&storage, &mounted), format!("{}\n", mounted.display()));
+    // Directories that are not mounted are found under the root of the host
+    assert_eq!(pwd(&storage, Path::new("/etc")), "/host/etc\n");
+}